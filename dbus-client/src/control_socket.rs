@@ -0,0 +1,25 @@
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// `$XDG_RUNTIME_DIR/divera-status.sock` of a daemon started with --control-socket.
+pub fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("divera-status.sock"))
+}
+
+/// Send a command line to the daemon and return its answer.
+pub fn send(path: &Path, command: &str) -> Result<String, io::Error> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_millis(5000)))?;
+    writeln!(stream, "{}", command)?;
+
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer)?;
+    let answer = answer.trim_end().to_string();
+    match answer.strip_prefix("error: ") {
+        Some(reason) => Err(io::Error::other(reason.to_string())),
+        None => Ok(answer),
+    }
+}
@@ -15,6 +15,15 @@ impl<'a, T: blocking::BlockingSender, C: std::ops::Deref<Target = T>> DeNlihDive
 pub trait DeNlihDiveraStatus1Status {
     fn next(&self) -> Result<(), dbus::Error>;
     fn previous(&self) -> Result<(), dbus::Error>;
+    fn next_account(&self, account: &str) -> Result<(), dbus::Error>;
+    fn previous_account(&self, account: &str) -> Result<(), dbus::Error>;
+    fn set(&self, status: &str) -> Result<(), dbus::Error>;
+    fn set_account(&self, account: &str, status: &str) -> Result<(), dbus::Error>;
+    fn list_statuses(&self) -> Result<Vec<(u32, String, String)>, dbus::Error>;
+    fn list_statuses_account(
+        &self,
+        account: &str,
+    ) -> Result<Vec<(u32, String, String)>, dbus::Error>;
 }
 
 impl<'a, T: blocking::BlockingSender, C: std::ops::Deref<Target = T>> DeNlihDiveraStatus1Status
@@ -27,4 +36,81 @@ impl<'a, T: blocking::BlockingSender, C: std::ops::Deref<Target = T>> DeNlihDive
     fn previous(&self) -> Result<(), dbus::Error> {
         self.method_call("de.nlih.DiveraStatus1.Status", "Previous", ())
     }
+
+    fn next_account(&self, account: &str) -> Result<(), dbus::Error> {
+        self.method_call("de.nlih.DiveraStatus1.Status", "NextAccount", (account,))
+    }
+
+    fn previous_account(&self, account: &str) -> Result<(), dbus::Error> {
+        self.method_call(
+            "de.nlih.DiveraStatus1.Status",
+            "PreviousAccount",
+            (account,),
+        )
+    }
+
+    fn set(&self, status: &str) -> Result<(), dbus::Error> {
+        self.method_call("de.nlih.DiveraStatus1.Status", "Set", (status,))
+    }
+
+    fn set_account(&self, account: &str, status: &str) -> Result<(), dbus::Error> {
+        self.method_call(
+            "de.nlih.DiveraStatus1.Status",
+            "SetAccount",
+            (account, status),
+        )
+    }
+
+    fn list_statuses(&self) -> Result<Vec<(u32, String, String)>, dbus::Error> {
+        self.method_call("de.nlih.DiveraStatus1.Status", "ListStatuses", ())
+            .map(|r: (Vec<(u32, String, String)>,)| r.0)
+    }
+
+    fn list_statuses_account(
+        &self,
+        account: &str,
+    ) -> Result<Vec<(u32, String, String)>, dbus::Error> {
+        self.method_call(
+            "de.nlih.DiveraStatus1.Status",
+            "ListStatusesAccount",
+            (account,),
+        )
+        .map(|r: (Vec<(u32, String, String)>,)| r.0)
+    }
+}
+
+pub trait DeNlihDiveraStatus1News {
+    fn mark_all_read(&self) -> Result<(), dbus::Error>;
+}
+
+impl<'a, T: blocking::BlockingSender, C: std::ops::Deref<Target = T>> DeNlihDiveraStatus1News
+    for blocking::Proxy<'a, C>
+{
+    fn mark_all_read(&self) -> Result<(), dbus::Error> {
+        self.method_call("de.nlih.DiveraStatus1.News", "MarkAllRead", ())
+    }
+}
+
+pub trait DeNlihDiveraStatus1Event {
+    fn respond(&self, id: u32, accept: bool) -> Result<(), dbus::Error>;
+}
+
+impl<'a, T: blocking::BlockingSender, C: std::ops::Deref<Target = T>> DeNlihDiveraStatus1Event
+    for blocking::Proxy<'a, C>
+{
+    fn respond(&self, id: u32, accept: bool) -> Result<(), dbus::Error> {
+        self.method_call("de.nlih.DiveraStatus1.Event", "Respond", (id, accept))
+    }
+}
+
+pub trait DeNlihDiveraStatus1Vehicle {
+    fn set_status(&self, id: u32, fms: u8) -> Result<(), dbus::Error>;
+}
+
+impl<'a, T: blocking::BlockingSender, C: std::ops::Deref<Target = T>> DeNlihDiveraStatus1Vehicle
+    for blocking::Proxy<'a, C>
+{
+    fn set_status(&self, id: u32, fms: u8) -> Result<(), dbus::Error> {
+        self.method_call("de.nlih.DiveraStatus1.Vehicle", "SetStatus", (id, fms))
+    }
 }
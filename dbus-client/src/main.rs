@@ -1,10 +1,13 @@
+mod control_socket;
 mod divera_status1;
 
 use crate::divera_status1::*;
 
+use std::io::Write;
+use std::process::{Command, Stdio};
 use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use dbus::blocking::Connection;
 
 #[derive(Parser)]
@@ -12,6 +15,10 @@ use dbus::blocking::Connection;
 struct Arguments {
     #[command(subcommand)]
     command: Commands,
+
+    /// use the control socket of the daemon instead of dbus, the fallback if dbus is not available
+    #[arg(long)]
+    socket: bool,
 }
 
 #[derive(Subcommand)]
@@ -19,26 +26,223 @@ enum Commands {
     /// reload data from api
     Update,
     /// set next status in order
-    Next,
+    Next {
+        /// account to change, defaults to the first one
+        #[arg(short, long)]
+        account: Option<String>,
+    },
     /// set previous status in order
-    Prev,
+    Prev {
+        /// account to change, defaults to the first one
+        #[arg(short, long)]
+        account: Option<String>,
+    },
+    /// choose the status with a launcher like dmenu, rofi or fuzzel, needs dbus
+    Pick {
+        /// command that reads the status names from stdin and prints the chosen one
+        #[arg(short, long, default_value = "rofi -dmenu")]
+        launcher: String,
+        /// account to change, defaults to the first one
+        #[arg(short, long)]
+        account: Option<String>,
+    },
+    /// news of all accounts
+    News {
+        #[command(subcommand)]
+        command: NewsCommands,
+    },
+    /// events of all accounts
+    Event {
+        #[command(subcommand)]
+        command: EventCommands,
+    },
+    /// vehicles of the unit
+    Vehicle {
+        #[command(subcommand)]
+        command: VehicleCommands,
+    },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Arguments::parse();
+#[derive(Subcommand)]
+enum NewsCommands {
+    /// mark all news as read
+    Read,
+}
+
+#[derive(Subcommand)]
+enum EventCommands {
+    /// accept or decline the participation in an event
+    Respond {
+        /// id of the event, see `divera-status events`
+        id: u32,
+        #[arg(value_enum)]
+        answer: Answer,
+    },
+}
+
+#[derive(Subcommand)]
+enum VehicleCommands {
+    /// set the FMS status of a vehicle, needs the permission in the unit
+    Set {
+        /// id of the vehicle
+        id: u32,
+        /// FMS status code
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=9))]
+        fms: u8,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Answer {
+    Accept,
+    Decline,
+}
+
+/// Whether the daemon can not be reached over dbus at all.
+fn unreachable(err: &dbus::Error) -> bool {
+    matches!(
+        err.name(),
+        Some(
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+                | "org.freedesktop.DBus.Error.NameHasNoOwner"
+        )
+    )
+}
 
+fn call_dbus(conn: &Connection, command: &Commands) -> Result<(), dbus::Error> {
+    let proxy = conn.with_proxy(
+        "de.nlih.diverastatus",
+        "/de/nlih/DiveraStatus1",
+        Duration::from_millis(5000),
+    );
+
+    match command {
+        Commands::Update => proxy.update(),
+        Commands::Next { account: None } => proxy.next(),
+        Commands::Next {
+            account: Some(account),
+        } => proxy.next_account(account),
+        Commands::Prev { account: None } => proxy.previous(),
+        Commands::Prev {
+            account: Some(account),
+        } => proxy.previous_account(account),
+        Commands::Pick { .. } => unreachable!("pick is handled in main"),
+        Commands::News {
+            command: NewsCommands::Read,
+        } => proxy.mark_all_read(),
+        Commands::Event {
+            command: EventCommands::Respond { id, answer },
+        } => proxy.respond(*id, matches!(answer, Answer::Accept)),
+        Commands::Vehicle {
+            command: VehicleCommands::Set { id, fms },
+        } => proxy.set_status(*id, *fms),
+    }
+}
+
+/// The same command for the control socket.
+fn socket_command(command: &Commands) -> String {
+    match command {
+        Commands::Update => "reload".to_string(),
+        Commands::Next { account: None } => "next".to_string(),
+        Commands::Next {
+            account: Some(account),
+        } => format!("next {}", account),
+        Commands::Prev { account: None } => "prev".to_string(),
+        Commands::Prev {
+            account: Some(account),
+        } => format!("prev {}", account),
+        Commands::Pick { .. } => unreachable!("pick is handled in main"),
+        Commands::News {
+            command: NewsCommands::Read,
+        } => "news-read".to_string(),
+        Commands::Event {
+            command: EventCommands::Respond { id, answer },
+        } => format!(
+            "event-respond {} {}",
+            id,
+            match answer {
+                Answer::Accept => "accept",
+                Answer::Decline => "decline",
+            }
+        ),
+        Commands::Vehicle {
+            command: VehicleCommands::Set { id, fms },
+        } => format!("vehicle-set {} {}", id, fms),
+    }
+}
+
+/// Let the user choose one of the statuses of the daemon with the launcher and set it.
+fn pick(launcher: &str, account: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
     let conn = Connection::new_session()?;
     let proxy = conn.with_proxy(
         "de.nlih.diverastatus",
         "/de/nlih/DiveraStatus1",
         Duration::from_millis(5000),
     );
+    let statuses = match account {
+        Some(account) => proxy.list_statuses_account(account)?,
+        None => proxy.list_statuses()?,
+    };
+
+    let mut child = Command::new("sh")
+        .args(["-c", launcher])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let names: Vec<&str> = statuses.iter().map(|(_, name, _)| name.as_str()).collect();
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(format!("{}\n", names.join("\n")).as_bytes())?;
+    let output = child.wait_with_output()?;
+
+    // nothing chosen, e.g. closed with escape
+    let chosen = String::from_utf8_lossy(&output.stdout);
+    let chosen = chosen.trim();
+    if !output.status.success() || chosen.is_empty() {
+        return Ok(());
+    }
+    let (id, _, _) = statuses
+        .iter()
+        .find(|(_, name, _)| name == chosen)
+        .ok_or_else(|| format!("unknown status {}", chosen))?;
+
+    match account {
+        Some(account) => proxy.set_account(account, &id.to_string())?,
+        None => proxy.set(&id.to_string())?,
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Arguments::parse();
+
+    if let Commands::Pick { launcher, account } = &args.command {
+        if args.socket {
+            return Err("pick needs dbus, the control socket can not list the statuses".into());
+        }
+        return pick(launcher, account.as_deref());
+    }
 
-    match args.command {
-        Commands::Update => proxy.update()?,
-        Commands::Next => proxy.next()?,
-        Commands::Prev => proxy.previous()?,
+    if !args.socket {
+        let result = Connection::new_session()
+            .map_err(|err| (err, true))
+            .and_then(|conn| {
+                call_dbus(&conn, &args.command).map_err(|err| {
+                    let unreachable = unreachable(&err);
+                    (err, unreachable)
+                })
+            });
+        match result {
+            Ok(()) => return Ok(()),
+            // fall back to the control socket of a daemon without dbus
+            Err((_, true)) if control_socket::socket_path().is_some_and(|path| path.exists()) => {}
+            Err((err, _)) => return Err(err.into()),
+        }
     }
 
+    let path = control_socket::socket_path().ok_or("$XDG_RUNTIME_DIR is not set")?;
+    control_socket::send(&path, &socket_command(&args.command))?;
     Ok(())
 }
@@ -1,36 +1,115 @@
-use crate::api_types::{Monitor, Status, User, UserStatus, Wrapper};
+use crate::api_types::{MutableData, Status, User, UserStatus, Vehicle, Wrapper};
+use crate::error::Error;
+use crate::logging;
 
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use log::{debug, trace};
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 
 pub type UserMap = HashMap<String, User>;
 pub type StatusMap = HashMap<String, Status>;
 
+/// Longest time a request may take, including connecting.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before 1970")
+        .as_secs() as i64
+}
+
+/// Counters of the requests made with the token.
+#[derive(Debug, Default)]
+pub struct RequestStats {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    duration_micros: AtomicU64,
+}
+
+// only read for the metrics
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+impl RequestStats {
+    fn record<T>(&self, started: Instant, result: &Result<T, reqwest::Error>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.duration_micros
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        if result.is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Summed up time of all requests.
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.duration_micros.load(Ordering::Relaxed))
+    }
+}
+
 #[derive(Debug)]
 pub struct Connection {
     client: Client,
     server: String,
     token: String,
-    debug: bool,
+    /// hide the names of the members in the log as soon as they are pulled
+    redact_names: bool,
+    stats: RequestStats,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cluster {
+    consumer: HashMap<String, User>,
+    status: HashMap<String, Status>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullData {
+    cluster: Cluster,
 }
 
 impl Connection {
-    pub fn new(client: Client, server: String, token: String, debug: bool) -> Self {
+    pub fn new(client: Client, server: String, token: String, redact_names: bool) -> Self {
+        logging::redact(token.as_str());
         Connection {
             client,
             server,
             token,
-            debug,
+            redact_names,
+            stats: RequestStats::default(),
         }
     }
 
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub fn stats(&self) -> &RequestStats {
+        &self.stats
+    }
+
     async fn make_get_request<T: Serialize + ?Sized>(
         &self,
         endpoint: &str,
         query: &T,
+    ) -> Result<Response, reqwest::Error> {
+        let started = Instant::now();
+        let result = self.send_get_request(endpoint, query).await;
+        self.stats.record(started, &result);
+        result
+    }
+
+    async fn send_get_request<T: Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        query: &T,
     ) -> Result<Response, reqwest::Error> {
         let response = self
             .client
@@ -38,9 +117,12 @@ impl Connection {
             .query(query)
             .query(&[("accesskey", self.token.clone())])
             .send()
-            .await?;
+            .await
+            .map_err(reqwest::Error::without_url)?;
 
-        response.error_for_status_ref()?;
+        response
+            .error_for_status_ref()
+            .map_err(|err| err.without_url())?;
 
         Ok(response)
     }
@@ -49,6 +131,17 @@ impl Connection {
         &self,
         endpoint: &str,
         data: T,
+    ) -> Result<Response, reqwest::Error> {
+        let started = Instant::now();
+        let result = self.send_post_request(endpoint, data).await;
+        self.stats.record(started, &result);
+        result
+    }
+
+    async fn send_post_request<T: Into<reqwest::Body>>(
+        &self,
+        endpoint: &str,
+        data: T,
     ) -> Result<Response, reqwest::Error> {
         let response = self
             .client
@@ -57,36 +150,27 @@ impl Connection {
             .header("Content-Type", "application/json")
             .body(data)
             .send()
-            .await?;
+            .await
+            .map_err(reqwest::Error::without_url)?;
 
-        response.error_for_status_ref()?;
+        response
+            .error_for_status_ref()
+            .map_err(|err| err.without_url())?;
 
         Ok(response)
     }
 
-    pub async fn pull_static(&self) -> Result<(UserMap, StatusMap), reqwest::Error> {
-        #[derive(Debug, Deserialize)]
-        struct Cluster {
-            consumer: HashMap<String, User>,
-            status: HashMap<String, Status>,
-        }
-
-        #[derive(Debug, Deserialize)]
-        struct PullData {
-            cluster: Cluster,
-        }
+    /// Check that the server answers at all, without using the token.
+    pub async fn ping(&self) -> Result<(), reqwest::Error> {
+        debug!("pinging {}", self.server);
+        self.client.head(self.server.clone()).send().await?;
+        Ok(())
+    }
 
-        if self.debug {
-            println!("debug: pulling static data");
-        }
+    pub async fn pull_static(&self) -> Result<(UserMap, StatusMap), reqwest::Error> {
+        debug!("pulling static data");
 
-        let time_now = format!(
-            "{}",
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("System time is before 1970")
-                .as_secs()
-        );
+        let time_now = unix_now().to_string();
 
         let query = [
             ("ts_user", time_now.as_str()),
@@ -99,54 +183,135 @@ impl Connection {
             ("ts_monitor", time_now.as_str()),
         ];
         let response = self.make_get_request("/api/v2/pull/all", &query).await?;
-        let pull_data: Wrapper<PullData> = response.json().await?;
+        let pull_data: Wrapper<PullData> =
+            response.json().await.map_err(reqwest::Error::without_url)?;
 
-        if self.debug {
-            println!("debug: got pull data: {:?}", pull_data);
+        Ok(self.static_maps(pull_data))
+    }
+
+    /// Split the static data, the names are redacted before anything of it is logged.
+    fn static_maps(&self, pull_data: Wrapper<PullData>) -> (UserMap, StatusMap) {
+        if self.redact_names {
+            for user in pull_data.data.cluster.consumer.values() {
+                logging::redact(user.name().as_str());
+            }
         }
 
-        Ok((
+        trace!("got pull data: {:?}", pull_data);
+
+        (
             pull_data.data.cluster.consumer,
             pull_data.data.cluster.status,
-        ))
+        )
     }
 
-    pub async fn pull_mutable(&self) -> Result<(Monitor, UserStatus), reqwest::Error> {
-        #[derive(Debug, Deserialize)]
-        struct PullData {
-            monitor: Monitor,
-            status: UserStatus,
-        }
-
-        if self.debug {
-            println!("debug: pulling mutable data");
-        }
+    pub async fn pull_mutable(&self) -> Result<MutableData, reqwest::Error> {
+        debug!("pulling mutable data");
 
-        let time_now = format!(
-            "{}",
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("System time is before 1970")
-                .as_secs()
-        );
+        let time_now = unix_now().to_string();
 
         let query = [
-            ("ts_user", time_now.as_str()),
-            ("ts_alarm", time_now.as_str()),
-            ("ts_news", time_now.as_str()),
-            ("ts_event", time_now.as_str()),
-            ("ts_statusplan", time_now.as_str()),
             ("ts_localmonitor", time_now.as_str()),
             ("ts_cluster", time_now.as_str()),
         ];
         let response = self.make_get_request("/api/v2/pull/all", &query).await?;
-        let pull_data: Wrapper<PullData> = response.json().await?;
+        let pull_data: Wrapper<MutableData> =
+            response.json().await.map_err(reqwest::Error::without_url)?;
+
+        trace!("got pull data: {:?}", pull_data);
+
+        Ok(pull_data.data)
+    }
+
+    pub async fn mark_news_read(&self, id: u32) -> Result<(), reqwest::Error> {
+        debug!("marking news {} as read", id);
+
+        self.make_post_request(&format!("/api/v2/news/read/{}", id), "{}")
+            .await?;
+        Ok(())
+    }
 
-        if self.debug {
-            println!("debug: got pull data: {:?}", pull_data);
+    pub async fn pull_vehicles(&self) -> Result<Vec<Vehicle>, reqwest::Error> {
+        debug!("pulling vehicles");
+
+        let query: [(&str, &str); 0] = [];
+        let response = self
+            .make_get_request("/api/v2/pull/vehicle-status", &query)
+            .await?;
+        let vehicles: Wrapper<Vec<Vehicle>> =
+            response.json().await.map_err(reqwest::Error::without_url)?;
+
+        trace!("got vehicles: {:?}", vehicles);
+
+        Ok(vehicles.data)
+    }
+
+    /// Set the FMS status of a vehicle, needs the permission to do so in the unit.
+    pub async fn set_vehicle_status(&self, id: u32, fms: u8) -> Result<(), reqwest::Error> {
+        #[derive(Serialize)]
+        struct VehicleStatus {
+            status: u8,
         }
 
-        Ok((pull_data.data.monitor, pull_data.data.status))
+        debug!("setting vehicle {} to fms {}", id, fms);
+
+        let body = serde_json::to_string(&VehicleStatus { status: fms }).unwrap();
+        self.make_post_request(&format!("/api/v2/using-vehicles/set-status/{}", id), body)
+            .await?;
+        Ok(())
+    }
+
+    /// Accept or decline the participation in an event.
+    pub async fn respond_event(&self, id: u32, accept: bool) -> Result<(), reqwest::Error> {
+        #[derive(Serialize)]
+        struct Participation {
+            /// 1 accepted, 2 declined
+            participation: u8,
+        }
+
+        #[derive(Serialize)]
+        struct Wrapper {
+            #[serde(rename = "Participation")]
+            participation: Participation,
+        }
+
+        debug!("responding to event {}: accept {}", id, accept);
+
+        let wrapper = Wrapper {
+            participation: Participation {
+                participation: if accept { 1 } else { 2 },
+            },
+        };
+
+        let body = serde_json::to_string(&wrapper).unwrap();
+        self.make_post_request(&format!("/api/v2/events/participation/{}", id), body)
+            .await?;
+        Ok(())
+    }
+
+    /// Answer an alarm with one of the response options of the unit.
+    pub async fn respond_alarm(&self, id: u32, response_id: u32) -> Result<(), reqwest::Error> {
+        #[derive(Serialize)]
+        struct Confirm {
+            response_id: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Wrapper {
+            #[serde(rename = "AlarmConfirm")]
+            confirm: Confirm,
+        }
+
+        debug!("responding to alarm {}: response {}", id, response_id);
+
+        let wrapper = Wrapper {
+            confirm: Confirm { response_id },
+        };
+
+        let body = serde_json::to_string(&wrapper).unwrap();
+        self.make_post_request(&format!("/api/v2/alarms/confirm/{}", id), body)
+            .await?;
+        Ok(())
     }
 
     pub async fn set_status(&self, data: UserStatus) -> Result<(), reqwest::Error> {
@@ -156,9 +321,7 @@ impl Connection {
             status: UserStatus,
         }
 
-        if self.debug {
-            println!("debug: setting status to: {}", data.status_id());
-        }
+        debug!("setting status to: {}", data.status_id());
 
         let wrapper = Wrapper { status: data };
 
@@ -171,4 +334,143 @@ impl Connection {
     pub async fn set_status_id(&self, id: u32) -> Result<(), reqwest::Error> {
         self.set_status(UserStatus::new(id)).await
     }
+
+    /// Log in with username and password and return the access key of the user.
+    ///
+    /// Does not need a token, so the connection can be created with an empty one.
+    pub async fn login(&self, username: &str, password: &str) -> Result<String, Error> {
+        #[derive(Serialize)]
+        struct Login<'a> {
+            username: &'a str,
+            password: &'a str,
+            jwt: bool,
+        }
+
+        #[derive(Serialize)]
+        struct Wrapper<'a> {
+            #[serde(rename = "Login")]
+            login: Login<'a>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct LoginUser {
+            access_token: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct LoginData {
+            user: LoginUser,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct LoginResponse {
+            #[serde(default)]
+            success: bool,
+            data: Option<LoginData>,
+            #[serde(default)]
+            errors: HashMap<String, serde_json::Value>,
+            message: Option<String>,
+        }
+
+        debug!("logging in as {}", username);
+        logging::redact(password);
+
+        let body = serde_json::to_string(&Wrapper {
+            login: Login {
+                username,
+                password,
+                jwt: false,
+            },
+        })
+        .unwrap();
+        let response = self
+            .client
+            .post(self.server.clone() + "/api/v2/auth/login")
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status();
+        let login: LoginResponse = response.json().await?;
+
+        if let (true, Some(data)) = (login.success, login.data) {
+            logging::redact(data.user.access_token.as_str());
+            return Ok(data.user.access_token);
+        }
+
+        let reason = login
+            .message
+            .or_else(|| {
+                login.errors.values().find_map(|value| match value {
+                    serde_json::Value::String(message) => Some(message.clone()),
+                    serde_json::Value::Array(messages) => messages
+                        .first()
+                        .and_then(|message| message.as_str())
+                        .map(String::from),
+                    _ => None,
+                })
+            })
+            .unwrap_or_else(|| format!("server answered with {}", status));
+        Err(Error::LoginFailed(reason))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::{Connection, PullData};
+    use crate::api_types::Wrapper;
+    use crate::logging;
+
+    use std::sync::Mutex;
+
+    /// Keeps the log lines like the redacting writer of the real logger would write them.
+    struct CaptureLogger {
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.lines
+                .lock()
+                .unwrap()
+                .push(logging::apply_redactions(&record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CaptureLogger = CaptureLogger {
+        lines: Mutex::new(Vec::new()),
+    };
+
+    #[test]
+    fn test_names_redacted_before_trace() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let pull_data: Wrapper<PullData> = serde_json::from_str(
+            r#"{"data": {"cluster": {
+                "consumer": {"6": {"stdformat_name": "Erika Musterfrau"}},
+                "status": {"1": {"name": "available", "color_hex": "0f0"}}
+            }}}"#,
+        )
+        .unwrap();
+        let connection =
+            Connection::new(reqwest::Client::new(), String::new(), String::new(), true);
+
+        let (user_map, _) = connection.static_maps(pull_data);
+        assert_eq!(user_map["6"].name(), "Erika Musterfrau");
+
+        let lines = LOGGER.lines.lock().unwrap();
+        let trace = lines
+            .iter()
+            .find(|line| line.starts_with("got pull data"))
+            .expect("pull data not logged");
+        assert!(trace.contains("[redacted]"));
+        assert!(!trace.contains("Erika"));
+    }
 }
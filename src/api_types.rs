@@ -1,13 +1,33 @@
 use std::collections::HashMap;
 
 use derive_getters::Getters;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Wrapper<T> {
     pub data: T,
 }
 
+/// The api sends an empty list instead of an empty object.
+pub(crate) fn map_or_empty_list<'de, D, T>(deserializer: D) -> Result<HashMap<String, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    enum MapOrList<T> {
+        Map(HashMap<String, T>),
+        List(Vec<serde::de::IgnoredAny>),
+    }
+
+    Ok(match MapOrList::deserialize(deserializer)? {
+        MapOrList::Map(map) => map,
+        MapOrList::List(_) => HashMap::new(),
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize, Getters, Clone)]
 pub struct User {
     #[serde(rename = "stdformat_name")]
@@ -57,3 +77,199 @@ impl UserStatus {
         Self { status_id }
     }
 }
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone)]
+pub struct News {
+    pub(crate) id: u32,
+    #[serde(default)]
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) text: String,
+    /// unix timestamp
+    #[serde(default)]
+    pub(crate) date: i64,
+    #[serde(default)]
+    pub(crate) read: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone, Default)]
+pub struct NewsList {
+    #[serde(default, deserialize_with = "map_or_empty_list")]
+    pub(crate) items: HashMap<String, News>,
+}
+
+impl NewsList {
+    pub fn unread(&self) -> impl Iterator<Item = &News> {
+        self.items.values().filter(|news| !news.read)
+    }
+
+    pub fn latest(&self) -> Option<&News> {
+        self.items.values().max_by_key(|news| (news.date, news.id))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone)]
+pub struct Event {
+    pub(crate) id: u32,
+    #[serde(default)]
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) text: String,
+    #[serde(default)]
+    pub(crate) address: String,
+    /// unix timestamp
+    pub(crate) start: i64,
+    /// unix timestamp
+    pub(crate) end: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone, Default)]
+pub struct EventList {
+    #[serde(default, deserialize_with = "map_or_empty_list")]
+    pub(crate) items: HashMap<String, Event>,
+}
+
+impl EventList {
+    /// Events that have not ended at `now`, the next one first.
+    pub fn upcoming(&self, now: i64) -> Vec<&Event> {
+        let mut events: Vec<&Event> = self
+            .items
+            .values()
+            .filter(|event| event.end > now)
+            .collect();
+        events.sort_by_key(|event| (event.start, event.id));
+        events
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Event> {
+        self.items.get(&id.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone)]
+pub struct Alarm {
+    pub(crate) id: u32,
+    #[serde(default)]
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) text: String,
+    /// unix timestamp
+    #[serde(default)]
+    pub(crate) date: i64,
+    #[serde(default)]
+    pub(crate) closed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone, Default)]
+pub struct AlarmList {
+    #[serde(default, deserialize_with = "map_or_empty_list")]
+    pub(crate) items: HashMap<String, Alarm>,
+}
+
+impl AlarmList {
+    pub fn open(&self) -> impl Iterator<Item = &Alarm> {
+        self.items.values().filter(|alarm| !alarm.closed)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Alarm> {
+        self.items.get(&id.to_string())
+    }
+}
+
+/// The logged in user.
+#[derive(Debug, Serialize, Deserialize, Getters, Clone)]
+pub struct OwnUser {
+    /// id of the user in the unit, as used in the monitor
+    #[serde(rename = "ucr_active")]
+    pub(crate) id: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone)]
+pub struct PlannedStatus {
+    pub(crate) id: u32,
+    #[serde(rename = "user_cluster_relation_id")]
+    pub(crate) user_id: u32,
+    pub(crate) status_id: u32,
+    /// unix timestamp
+    pub(crate) start: i64,
+    /// unix timestamp
+    pub(crate) end: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone, Default)]
+pub struct StatusPlan {
+    #[serde(default, deserialize_with = "map_or_empty_list")]
+    pub(crate) items: HashMap<String, PlannedStatus>,
+}
+
+impl StatusPlan {
+    /// The planned status of the user at unix time `at`, the latest planned one if they overlap.
+    pub fn status_at(&self, user_id: u32, at: i64) -> Option<u32> {
+        self.items
+            .values()
+            .filter(|item| item.user_id == user_id && item.start <= at && at < item.end)
+            .max_by_key(|item| (item.start, item.id))
+            .map(|item| item.status_id)
+    }
+
+    /// The next planned status of the user starting after `now`.
+    pub fn next_change(&self, user_id: u32, now: i64) -> Option<&PlannedStatus> {
+        self.items
+            .values()
+            .filter(|item| item.user_id == user_id && item.start > now)
+            .min_by_key(|item| (item.start, item.id))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Getters, Clone)]
+pub struct Vehicle {
+    pub(crate) id: u32,
+    #[serde(default)]
+    pub(crate) shortname: String,
+    #[serde(default)]
+    pub(crate) fullname: String,
+    #[serde(rename = "fmsstatus", default)]
+    pub(crate) fms: u8,
+}
+
+/// Data that changes between polls.
+#[derive(Debug, Deserialize, Getters, Clone)]
+pub struct MutableData {
+    pub(crate) monitor: Monitor,
+    pub(crate) status: UserStatus,
+    #[serde(default)]
+    pub(crate) news: NewsList,
+    #[serde(default, rename = "alarm")]
+    pub(crate) alarms: AlarmList,
+    #[serde(default, rename = "event")]
+    pub(crate) events: EventList,
+    #[serde(default)]
+    pub(crate) user: Option<OwnUser>,
+    #[serde(default)]
+    pub(crate) statusplan: StatusPlan,
+    /// pulled separately and only if used in the display format
+    #[serde(skip)]
+    pub(crate) vehicles: Vec<Vehicle>,
+}
+
+impl MutableData {
+    /// Ids of the users with one of the `statuses` at unix time `at`.
+    ///
+    /// Uses the status plan and falls back to the current status.
+    pub fn planned_users(&self, statuses: &[u32], at: i64) -> Vec<u32> {
+        let mut users: Vec<u32> = self
+            .monitor
+            .complex
+            .iter()
+            .flat_map(|(status_id, status)| {
+                let status_id: u32 = status_id.parse().unwrap_or_default();
+                status.users.iter().filter_map(move |user| {
+                    let planned = self.statusplan.status_at(user.id, at).unwrap_or(status_id);
+                    statuses.contains(&planned).then_some(user.id)
+                })
+            })
+            .collect();
+        users.sort();
+        users
+    }
+}
@@ -0,0 +1,217 @@
+use crate::ics;
+use crate::ics::CalendarEvent;
+use crate::rules::Switch;
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+/// A local iCalendar file whose events set the status.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalendarConfig {
+    pub file: PathBuf,
+    /// account to change, the first one if missing
+    pub account: Option<String>,
+    /// statuses for the events, the first matching one is used
+    #[serde(rename = "event")]
+    pub mappings: Vec<CalendarMapping>,
+}
+
+/// Statuses for events with a category or summary, for all events if both are missing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalendarMapping {
+    pub category: Option<String>,
+    pub summary: Option<String>,
+    /// id or name of the status at the start of the event
+    pub status: String,
+    /// id or name of the status at the end of the event
+    pub end_status: Option<String>,
+}
+
+impl CalendarMapping {
+    fn matches(&self, event: &CalendarEvent) -> bool {
+        let category_ok = self.category.as_ref().is_none_or(|category| {
+            event
+                .categories
+                .iter()
+                .any(|item| item.eq_ignore_ascii_case(category))
+        });
+        let summary_ok = self
+            .summary
+            .as_ref()
+            .is_none_or(|summary| event.summary.eq_ignore_ascii_case(summary));
+        category_ok && summary_ok
+    }
+}
+
+/// Events of a calendar file, read again when it changes.
+#[derive(Debug)]
+pub struct Calendar {
+    pub config: CalendarConfig,
+    modified: Option<SystemTime>,
+    events: Vec<CalendarEvent>,
+}
+
+impl Calendar {
+    pub fn new(config: CalendarConfig) -> Self {
+        let mut calendar = Calendar {
+            config,
+            modified: None,
+            events: Vec::new(),
+        };
+        calendar.reload();
+        calendar
+    }
+
+    /// Read the file if it was changed, keeps the old events if it can not be read.
+    pub fn reload(&mut self) {
+        let path = &self.config.file;
+        let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(err) => {
+                warn!("can not read calendar {}: {}", path.display(), err);
+                return;
+            }
+        };
+        if self.modified == Some(modified) {
+            return;
+        }
+
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                self.events = ics::parse(&content);
+                self.modified = Some(modified);
+                debug!(
+                    "read {} events from calendar {}",
+                    self.events.len(),
+                    path.display()
+                );
+            }
+            Err(err) => warn!("can not read calendar {}: {}", path.display(), err),
+        }
+    }
+
+    /// Status changes at the starts and ends of the events after `from` until and including `until`.
+    pub fn switches(&self, from: i64, until: i64) -> Vec<Switch> {
+        let mut switches = Vec::new();
+
+        for event in &self.events {
+            let Some(mapping) = self
+                .config
+                .mappings
+                .iter()
+                .find(|mapping| mapping.matches(event))
+            else {
+                continue;
+            };
+
+            let source = format!("calendar: {}", event.summary);
+            let mut add = |time: i64, status: &String| {
+                if from < time && time <= until {
+                    switches.push(Switch {
+                        time,
+                        account: self.config.account.clone(),
+                        status: status.clone(),
+                        source: source.clone(),
+                    });
+                }
+            };
+            for (start, end) in event.occurrences(from, until) {
+                add(start, &mapping.status);
+                if let Some(end_status) = &mapping.end_status {
+                    add(end, end_status);
+                }
+            }
+        }
+        switches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::calendar::{Calendar, CalendarConfig};
+    use crate::ics::{CalendarEvent, Recurrence};
+
+    #[test]
+    fn test_switches() {
+        let config: CalendarConfig = toml::from_str(
+            r#"
+            file = "/nonexistent/shifts.ics"
+
+            [[event]]
+            category = "duty"
+            status = "at work"
+            end_status = "available"
+
+            [[event]]
+            summary = "Vacation"
+            status = "unavailable"
+            "#,
+        )
+        .unwrap();
+        let mut calendar = Calendar::new(config);
+        for (summary, category, start) in [("Night", "Duty", 100), ("vacation", "", 200)] {
+            calendar.events.push(CalendarEvent {
+                summary: summary.to_string(),
+                categories: vec![category.to_string()],
+                start,
+                end: start + 50,
+                recurrence: None,
+                exceptions: vec![],
+            });
+        }
+
+        let switches: Vec<(i64, String)> = calendar
+            .switches(100, 1000)
+            .into_iter()
+            .map(|switch| (switch.time, switch.status))
+            .collect();
+        assert_eq!(
+            switches,
+            vec![
+                (150, "available".to_string()),
+                (200, "unavailable".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recurring_switches() {
+        let config: CalendarConfig = toml::from_str(
+            r#"
+            file = "/nonexistent/shifts.ics"
+
+            [[event]]
+            status = "at work"
+            "#,
+        )
+        .unwrap();
+        let mut calendar = Calendar::new(config);
+        // daily at 06:00 UTC from 1970-01-02, three times
+        calendar.events.push(CalendarEvent {
+            summary: "Shift".to_string(),
+            categories: vec![],
+            start: 86400 + 6 * 3600,
+            end: 86400 + 18 * 3600,
+            recurrence: Some(Recurrence {
+                days: 1,
+                count: Some(3),
+                until: None,
+                weekdays: vec![],
+                week_start: chrono::Weekday::Mon,
+            }),
+            exceptions: vec![],
+        });
+
+        let times: Vec<i64> = calendar
+            .switches(2 * 86400, 10 * 86400)
+            .into_iter()
+            .map(|switch| switch.time)
+            .collect();
+        assert_eq!(times, vec![2 * 86400 + 6 * 3600, 3 * 86400 + 6 * 3600]);
+    }
+}
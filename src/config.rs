@@ -0,0 +1,352 @@
+use crate::calendar::CalendarConfig;
+use crate::error::Error;
+use crate::resolve;
+use crate::rules::{ScheduleRule, SessionRule};
+
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+
+const TOKEN_ENV: &str = "DIVERA_TOKEN";
+
+pub(crate) fn default_server() -> String {
+    "https://app.divera247.com".to_string()
+}
+
+/// One divera account (unit) with its own token and statuses.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    /// prefix for the placeholders of this account, empty for the single account from the arguments
+    #[serde(default)]
+    pub name: String,
+    pub token: Option<String>,
+    pub token_file: Option<PathBuf>,
+    pub token_command: Option<String>,
+    #[serde(default)]
+    pub keyring: bool,
+    #[serde(default = "default_server")]
+    pub server: String,
+    pub shown_statuses: Option<String>,
+    pub status_order: Option<String>,
+    pub available_statuses: Option<String>,
+}
+
+/// Content of the --config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// replace the account from the arguments if not empty
+    #[serde(default, rename = "account")]
+    pub accounts: Vec<Account>,
+    #[serde(default, rename = "session_rule")]
+    pub session_rules: Vec<SessionRule>,
+    #[serde(default)]
+    pub schedule: Vec<ScheduleRule>,
+    #[serde(default, rename = "calendar")]
+    pub calendars: Vec<CalendarConfig>,
+    pub mqtt: Option<MqttConfig>,
+    #[serde(default, rename = "webhook")]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default, rename = "click")]
+    pub clicks: Vec<ClickBinding>,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_topic_prefix() -> String {
+    "divera-status".to_string()
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    AlarmOpen,
+    AlarmClose,
+    StatusChange,
+}
+
+fn all_webhook_events() -> Vec<WebhookEvent> {
+    vec![
+        WebhookEvent::AlarmOpen,
+        WebhookEvent::AlarmClose,
+        WebhookEvent::StatusChange,
+    ]
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+/// Url to post alarm and status changes to, needs feature `webhooks`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// events to post, all if missing
+    #[serde(default = "all_webhook_events")]
+    pub events: Vec<WebhookEvent>,
+    /// body with {}-placeholders, the event as json if missing
+    pub body: Option<String>,
+    /// key for the hmac-sha256 signature of the body in the X-Divera-Signature header
+    pub secret: Option<String>,
+    /// further attempts after a failed post, waiting twice as long each time
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickAction {
+    Reload,
+    Next,
+    Previous,
+    /// set `status`
+    Set,
+    /// open the web ui of the account
+    Open,
+    /// run `command` with sh
+    Run,
+    /// toggle hiding the member names in the output
+    Privacy,
+}
+
+/// Action for a click on the i3blocks block, needs feature `i3blocks`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClickBinding {
+    /// 1 left, 2 middle, 3 right, 4 and 5 mousewheel
+    pub button: u32,
+    /// modifiers held down, e.g. "Shift", "Control", "Mod1" or "Mod4"
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    /// only for a double click, a single click waits until a second one is no longer possible
+    #[serde(default)]
+    pub double: bool,
+    pub action: ClickAction,
+    /// account for next, previous, set and open, the first one if missing
+    pub account: Option<String>,
+    /// status id or name for set
+    pub status: Option<String>,
+    /// shell command for run
+    pub command: Option<String>,
+}
+
+/// Broker to publish the state to, needs feature `mqtt`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// topics are <topic_prefix>/<account>/..., the account is "default" for the one from the arguments
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    /// prefix of the home assistant discovery topics
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+impl Account {
+    pub fn get_token(&self) -> Result<String, io::Error> {
+        let token: String = if let Some(token) = &self.token {
+            token.clone()
+        } else if let Some(token_file) = &self.token_file {
+            let mut buffer = String::new();
+            let file = File::open(token_file)?;
+            let _ = io::BufReader::new(file).read_line(&mut buffer)?;
+            buffer.pop();
+            buffer
+        } else if let Some(token_command) = &self.token_command {
+            run_token_command(token_command)?
+        } else if self.keyring {
+            self.load_keyring_token()?
+        } else if let Ok(token) = std::env::var(TOKEN_ENV) {
+            token
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no token provided{}, use --token, --token-file, --token-command, --keyring or $DIVERA_TOKEN",
+                    self.describe()
+                ),
+            ));
+        };
+        Ok(token)
+    }
+
+    /// Whether a token source other than the keyring is configured.
+    pub fn has_token_source(&self) -> bool {
+        self.token.is_some()
+            || self.token_file.is_some()
+            || self.token_command.is_some()
+            || std::env::var_os(TOKEN_ENV).is_some()
+    }
+
+    /// Prefix a placeholder with the name of the account.
+    pub fn placeholder(&self, key: &str) -> String {
+        if self.name.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}_{}", self.name, key)
+        }
+    }
+
+    /// " for account <name>" for messages, empty for the single account.
+    pub fn describe(&self) -> String {
+        if self.name.is_empty() {
+            String::new()
+        } else {
+            format!(" for account {}", self.name)
+        }
+    }
+
+    #[cfg(feature = "secret-service")]
+    fn load_keyring_token(&self) -> Result<String, io::Error> {
+        crate::keyring::load_token(&self.server)
+    }
+
+    #[cfg(not(feature = "secret-service"))]
+    fn load_keyring_token(&self) -> Result<String, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--keyring needs the feature `secret-service`",
+        ))
+    }
+}
+
+fn run_token_command(command: &str) -> Result<String, io::Error> {
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "token command failed with {}",
+            output.status
+        )));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "token command printed nothing"))
+}
+
+fn check_accounts(accounts: &[Account]) -> Result<(), Error> {
+    let mut problems: Vec<String> = Vec::new();
+
+    for (index, account) in accounts.iter().enumerate() {
+        if account.name.is_empty() {
+            problems.push(format!("account {} has no name", index + 1));
+        } else if resolve::alias(&account.name) != account.name {
+            problems.push(format!(
+                "account name {} can not be used as placeholder prefix, try {}",
+                account.name,
+                resolve::alias(&account.name)
+            ));
+        } else if accounts[..index]
+            .iter()
+            .any(|other| other.name == account.name)
+        {
+            problems.push(format!("account {} is configured twice", account.name));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidConfig(problems))
+    }
+}
+
+/// Read the toml config file.
+pub fn load(path: &Path) -> Result<Config, Error> {
+    let content = std::fs::read_to_string(path)?;
+    let config: Config = toml::from_str(&content).map_err(|err| {
+        Error::InvalidConfig(vec![format!("{}: {}", path.display(), err.message())])
+    })?;
+
+    if !config.accounts.is_empty() {
+        check_accounts(&config.accounts)?;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{check_accounts, Config};
+    use crate::error::Error;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[account]]
+            name = "fw"
+            token_file = "/run/secrets/fw"
+            shown_statuses = "available,at work"
+            status_order = "1,2,3"
+            available_statuses = "available"
+
+            [[account]]
+            name = "rd"
+            token_command = "pass show divera/rd"
+            server = "https://divera.example.org"
+
+            [[session_rule]]
+            on = "lock"
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            from = "17:00"
+            status = "available"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.accounts.len(), 2);
+        assert_eq!(config.accounts[0].server, "https://app.divera247.com");
+        assert_eq!(config.accounts[1].server, "https://divera.example.org");
+        assert_eq!(config.accounts[1].placeholder("full_text"), "rd_full_text");
+        assert!(check_accounts(&config.accounts).is_ok());
+        assert_eq!(config.session_rules[0].days.len(), 5);
+        assert!(config.session_rules[0].until.is_none());
+    }
+
+    #[test]
+    fn test_check_accounts() {
+        let config: Config = toml::from_str(
+            r#"
+            [[account]]
+            name = "fw"
+            [[account]]
+            name = "fw"
+            [[account]]
+            [[account]]
+            name = "Rettungsdienst Nord"
+            "#,
+        )
+        .unwrap();
+
+        match check_accounts(&config.accounts) {
+            Err(Error::InvalidConfig(problems)) => assert_eq!(problems.len(), 3),
+            other => panic!("expected invalid config, got {:?}", other),
+        }
+    }
+}
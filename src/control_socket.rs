@@ -0,0 +1,173 @@
+#![cfg(feature = "control-socket")]
+
+use crate::socket;
+use crate::types::Update;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+
+/// Last output line of the daemon, for `get`.
+pub type Output = Arc<Mutex<String>>;
+
+/// What a command line asks for.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Get,
+    Update(Update),
+}
+
+/// `$XDG_RUNTIME_DIR/divera-status.sock`, also used by divera-dbus-client.
+pub fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("divera-status.sock"))
+}
+
+fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+    let account = (!rest.is_empty()).then(|| rest.to_string());
+
+    let update = match command {
+        "get" => return Ok(Command::Get),
+        "reload" => Update::Reload,
+        "next" => Update::StatusNext(account),
+        "prev" => Update::StatusPrev(account),
+        "set" if !rest.is_empty() => Update::SetStatus(None, rest.to_string(), "remote"),
+        "set-account" => match rest.split_once(' ') {
+            Some((account, status)) => Update::SetStatus(
+                Some(account.to_string()),
+                status.trim().to_string(),
+                "remote",
+            ),
+            None => return Err("usage: set-account <account> <status>".to_string()),
+        },
+        "news-read" => Update::NewsRead,
+        "event-respond" => match rest.split_once(' ') {
+            Some((id, answer @ ("accept" | "decline"))) => Update::EventResponse(
+                id.parse().map_err(|_| format!("invalid event id {}", id))?,
+                answer == "accept",
+            ),
+            _ => return Err("usage: event-respond <id> accept|decline".to_string()),
+        },
+        "vehicle-set" => match rest.split_once(' ') {
+            Some((id, fms)) => match (id.parse(), fms.parse()) {
+                (Ok(id), Ok(fms @ 0..=9)) => Update::VehicleStatus(id, fms),
+                _ => return Err("usage: vehicle-set <id> <fms 0-9>".to_string()),
+            },
+            None => return Err("usage: vehicle-set <id> <fms 0-9>".to_string()),
+        },
+        "set" => return Err("usage: set <status>".to_string()),
+        _ => return Err(format!("unknown command {}", command)),
+    };
+    Ok(Command::Update(update))
+}
+
+/// Answer every line of a connection with "ok", "error: <reason>" or the output for `get`.
+async fn handle(
+    stream: UnixStream,
+    tx: &mpsc::Sender<Update>,
+    output: &Output,
+) -> Result<(), std::io::Error> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        debug!("control socket command: {}", line);
+        let answer = match parse(&line) {
+            Ok(Command::Get) => output.lock().unwrap().clone(),
+            Ok(Command::Update(update)) => match tx.send(update).await {
+                Ok(()) => "ok".to_string(),
+                Err(_) => "error: daemon is shutting down".to_string(),
+            },
+            Err(err) => format!("error: {}", err),
+        };
+        writer.write_all(format!("{}\n", answer).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Accept line based commands on the control socket.
+pub async fn setup(tx: mpsc::Sender<Update>) -> Result<Output, std::io::Error> {
+    let path = socket_path().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "the control socket needs $XDG_RUNTIME_DIR",
+        )
+    })?;
+    debug!("listening on control socket {}", path.display());
+
+    socket::remove_stale_socket(&path)?;
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    let output = Output::default();
+    let shared = output.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let (tx, output) = (tx.clone(), shared.clone());
+                    tokio::spawn(async move {
+                        if let Err(err) = handle(stream, &tx, &output).await {
+                            debug!("error on control socket connection: {}", err);
+                        }
+                    });
+                }
+                Err(err) => warn!("error accepting control socket connection: {}", err),
+            }
+        }
+    });
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Command};
+    use crate::types::Update;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("get"), Ok(Command::Get));
+        assert_eq!(
+            parse("next\n"),
+            Ok(Command::Update(Update::StatusNext(None)))
+        );
+        assert_eq!(
+            parse("prev fw"),
+            Ok(Command::Update(Update::StatusPrev(Some("fw".to_string()))))
+        );
+        assert_eq!(
+            parse("set 3"),
+            Ok(Command::Update(Update::SetStatus(
+                None,
+                "3".to_string(),
+                "remote"
+            )))
+        );
+        assert_eq!(
+            parse("set-account fw at work"),
+            Ok(Command::Update(Update::SetStatus(
+                Some("fw".to_string()),
+                "at work".to_string(),
+                "remote"
+            )))
+        );
+        assert_eq!(
+            parse("event-respond 12 decline"),
+            Ok(Command::Update(Update::EventResponse(12, false)))
+        );
+        assert_eq!(
+            parse("vehicle-set 4 2"),
+            Ok(Command::Update(Update::VehicleStatus(4, 2)))
+        );
+
+        assert!(parse("set").is_err());
+        assert!(parse("vehicle-set 4 12").is_err());
+        assert!(parse("jump").is_err());
+    }
+}
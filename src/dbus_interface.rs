@@ -1,21 +1,35 @@
 #![cfg(feature = "dbus-interface")]
 
+use crate::divera_status1::{
+    register_de_nlih_divera_status1_event, register_de_nlih_divera_status1_monitor,
+    register_de_nlih_divera_status1_news, register_de_nlih_divera_status1_status,
+    register_de_nlih_divera_status1_vehicle, DeNlihDiveraStatus1Event, DeNlihDiveraStatus1Monitor,
+    DeNlihDiveraStatus1News, DeNlihDiveraStatus1Status, DeNlihDiveraStatus1Vehicle,
+};
+use crate::rules::{session_update, SessionEvent, SessionRule};
 use crate::types::Update;
-use crate::divera_status1::{DeNlihDiveraStatus1Monitor, DeNlihDiveraStatus1Status, register_de_nlih_divera_status1_monitor, register_de_nlih_divera_status1_status};
 
 use std::sync::Arc;
+use std::time::Duration;
 
+use dbus::arg::{prop_cast, PropMap, ReadAll};
 use dbus::channel::MatchingReceiver;
 use dbus::message::MatchRule;
+use dbus::nonblock::{Proxy, SyncConnection};
 use dbus::MethodErr;
-use dbus::nonblock::SyncConnection;
-use dbus_crossroads::{Crossroads};
+use dbus_crossroads::Crossroads;
 use dbus_tokio::connection;
+use log::{debug, warn};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::TrySendError;
 
+/// Id, name and color of a status.
+pub type StatusEntry = (u32, String, String);
+
 struct DbusData {
     tx: mpsc::Sender<Update>,
+    /// names and statuses of the accounts
+    accounts: Vec<(String, Vec<StatusEntry>)>,
 }
 
 impl DbusData {
@@ -25,6 +39,25 @@ impl DbusData {
             TrySendError::Closed(_) => panic!("update channel was closed"),
         })
     }
+
+    fn check_account(&self, account: String) -> Result<Option<String>, MethodErr> {
+        if self.accounts.iter().any(|(name, _)| *name == account) {
+            Ok(Some(account))
+        } else {
+            Err(MethodErr::invalid_arg(&account))
+        }
+    }
+
+    /// Statuses of the account, the first one without an account.
+    fn statuses(&self, account: Option<&str>) -> Result<Vec<StatusEntry>, MethodErr> {
+        let found = match account {
+            Some(account) => self.accounts.iter().find(|(name, _)| name == account),
+            None => self.accounts.first(),
+        };
+        found
+            .map(|(_, statuses)| statuses.clone())
+            .ok_or_else(|| MethodErr::invalid_arg(&account.unwrap_or_default()))
+    }
 }
 
 impl DeNlihDiveraStatus1Monitor for DbusData {
@@ -35,18 +68,240 @@ impl DeNlihDiveraStatus1Monitor for DbusData {
 
 impl DeNlihDiveraStatus1Status for DbusData {
     fn next(&mut self) -> Result<(), MethodErr> {
-        self.send_update(Update::StatusNext)
+        self.send_update(Update::StatusNext(None))
     }
 
     fn previous(&mut self) -> Result<(), MethodErr> {
-        self.send_update(Update::StatusPrev)
+        self.send_update(Update::StatusPrev(None))
+    }
+
+    fn next_account(&mut self, account: String) -> Result<(), MethodErr> {
+        let account = self.check_account(account)?;
+        self.send_update(Update::StatusNext(account))
+    }
+
+    fn previous_account(&mut self, account: String) -> Result<(), MethodErr> {
+        let account = self.check_account(account)?;
+        self.send_update(Update::StatusPrev(account))
+    }
+
+    fn set(&mut self, status: String) -> Result<(), MethodErr> {
+        self.send_update(Update::SetStatus(None, status, "remote"))
+    }
+
+    fn set_account(&mut self, account: String, status: String) -> Result<(), MethodErr> {
+        let account = self.check_account(account)?;
+        self.send_update(Update::SetStatus(account, status, "remote"))
+    }
+
+    fn list_statuses(&mut self) -> Result<Vec<StatusEntry>, MethodErr> {
+        self.statuses(None)
+    }
+
+    fn list_statuses_account(&mut self, account: String) -> Result<Vec<StatusEntry>, MethodErr> {
+        self.statuses(Some(&account))
     }
 }
 
-pub async fn setup(tx: mpsc::Sender<Update>, debug: bool) {
-    if debug {
-        println!("debug: setting up dbus connection")
+impl DeNlihDiveraStatus1News for DbusData {
+    fn mark_all_read(&mut self) -> Result<(), MethodErr> {
+        self.send_update(Update::NewsRead)
     }
+}
+
+impl DeNlihDiveraStatus1Event for DbusData {
+    fn respond(&mut self, id: u32, accept: bool) -> Result<(), MethodErr> {
+        self.send_update(Update::EventResponse(id, accept))
+    }
+}
+
+impl DeNlihDiveraStatus1Vehicle for DbusData {
+    fn set_status(&mut self, id: u32, fms: u8) -> Result<(), MethodErr> {
+        if fms > 9 {
+            return Err(MethodErr::invalid_arg(&fms));
+        }
+        self.send_update(Update::VehicleStatus(id, fms))
+    }
+}
+
+/// NM_STATE_CONNECTED_GLOBAL of NetworkManager
+const NM_STATE_CONNECTED_GLOBAL: u32 = 70;
+
+/// logind sends PrepareForSleep(true) before suspending and PrepareForSleep(false) after resuming.
+fn sleep_update((start,): (bool,)) -> Option<Update> {
+    (!start).then_some(Update::Reload)
+}
+
+fn network_update((state,): (u32,)) -> Option<Update> {
+    (state == NM_STATE_CONNECTED_GLOBAL).then_some(Update::Reload)
+}
+
+/// Send the update for every matching signal into the channel.
+///
+/// `arg0` is only checked by the bus, `to_update` has to check it again.
+async fn watch_signal<A: ReadAll + 'static>(
+    con: &SyncConnection,
+    rule: MatchRule<'static>,
+    arg0: Option<&str>,
+    tx: mpsc::Sender<Update>,
+    to_update: impl Fn(A) -> Option<Update> + Send + 'static,
+) -> Result<(), dbus::Error> {
+    let mut match_str = rule.match_str();
+    if let Some(arg0) = arg0 {
+        match_str.push_str(&format!(",arg0='{}'", arg0));
+    }
+    con.add_match_no_cb(&match_str).await?;
+    con.start_receive(
+        rule,
+        Box::new(move |msg, _| {
+            if let Some(update) = msg.read_all::<A>().ok().and_then(&to_update) {
+                debug!("got {:?} from signal {:?}", update, msg.member());
+                if let Err(err) = tx.try_send(update) {
+                    warn!("could not send update from signal: {}", err);
+                }
+            }
+            true
+        }),
+    );
+    Ok(())
+}
+
+/// Idle hint from a PropertiesChanged signal of a logind session.
+fn idle_event((interface, changed, _): (String, PropMap, Vec<String>)) -> Option<SessionEvent> {
+    if interface != LOGIND_SESSION {
+        return None;
+    }
+    prop_cast::<bool>(&changed, "IdleHint").map(|idle| {
+        if *idle {
+            SessionEvent::Idle
+        } else {
+            SessionEvent::Active
+        }
+    })
+}
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_SESSION: &str = "org.freedesktop.login1.Session";
+const NETWORK_MANAGER: &str = "org.freedesktop.NetworkManager";
+
+/// Object path of the logind session of this process, by $XDG_SESSION_ID or the pid.
+async fn own_session(con: &SyncConnection) -> Result<dbus::Path<'static>, dbus::Error> {
+    let proxy = Proxy::new(
+        LOGIND,
+        "/org/freedesktop/login1",
+        Duration::from_secs(5),
+        con,
+    );
+    let manager = "org.freedesktop.login1.Manager";
+    let (path,): (dbus::Path<'static>,) = match std::env::var("XDG_SESSION_ID") {
+        Ok(id) => proxy.method_call(manager, "GetSession", (id,)).await?,
+        Err(_) => {
+            proxy
+                .method_call(manager, "GetSessionByPID", (std::process::id(),))
+                .await?
+        }
+    };
+    Ok(path)
+}
+
+/// Turn lock, unlock and idle changes of the own session into status updates by the rules.
+async fn watch_session(
+    con: &SyncConnection,
+    tx: mpsc::Sender<Update>,
+    rules: Vec<SessionRule>,
+) -> Result<(), dbus::Error> {
+    let session = own_session(con).await?;
+    debug!("watching logind session {}", session);
+
+    let rules = Arc::new(rules);
+    let now = || chrono::Local::now().naive_local();
+
+    for (member, event) in [
+        ("Lock", SessionEvent::Lock),
+        ("Unlock", SessionEvent::Unlock),
+    ] {
+        let rules = rules.clone();
+        watch_signal(
+            con,
+            MatchRule::new_signal(LOGIND_SESSION, member)
+                .with_sender(LOGIND)
+                .with_path(session.clone()),
+            None,
+            tx.clone(),
+            move |()| session_update(&rules, event, now()),
+        )
+        .await?;
+    }
+
+    watch_signal(
+        con,
+        MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+            .with_sender(LOGIND)
+            .with_path(session),
+        Some(LOGIND_SESSION),
+        tx,
+        move |args| idle_event(args).and_then(|event| session_update(&rules, event, now())),
+    )
+    .await
+}
+
+async fn watch_system(con: &SyncConnection, tx: mpsc::Sender<Update>) -> Result<(), dbus::Error> {
+    watch_signal(
+        con,
+        MatchRule::new_signal("org.freedesktop.login1.Manager", "PrepareForSleep")
+            .with_sender(LOGIND)
+            .with_path("/org/freedesktop/login1"),
+        None,
+        tx.clone(),
+        sleep_update,
+    )
+    .await?;
+    watch_signal(
+        con,
+        MatchRule::new_signal(NETWORK_MANAGER, "StateChanged")
+            .with_sender(NETWORK_MANAGER)
+            .with_path("/org/freedesktop/NetworkManager"),
+        None,
+        tx,
+        network_update,
+    )
+    .await
+}
+
+/// Reload after resuming from suspend and after the network is connected again, set the status by the session rules.
+///
+/// Works without a system bus, there are just no reloads then.
+pub async fn setup_system(tx: mpsc::Sender<Update>, session_rules: Vec<SessionRule>) {
+    debug!("setting up system dbus connection");
+
+    let (resource, con): (connection::IOResource<SyncConnection>, Arc<SyncConnection>) =
+        match connection::new_system_sync() {
+            Ok(data) => data,
+            Err(err) => {
+                warn!("error connecting to system dbus: {}", err);
+                return;
+            }
+        };
+    let _handle = tokio::spawn(async {
+        let err = resource.await;
+        warn!("lost connection to system dbus: {}", err)
+    });
+
+    if let Err(err) = watch_system(&con, tx.clone()).await {
+        warn!("error watching system dbus signals: {}", err);
+    }
+    if !session_rules.is_empty() {
+        for rule in &session_rules {
+            debug!("using session rule: {:?}", rule);
+        }
+        if let Err(err) = watch_session(&con, tx, session_rules).await {
+            warn!("error watching logind sessions: {}", err);
+        }
+    }
+}
+
+pub async fn setup(tx: mpsc::Sender<Update>, accounts: Vec<(String, Vec<StatusEntry>)>) {
+    debug!("setting up dbus connection");
 
     // set up async dbus connection
     let (resource, con): (connection::IOResource<SyncConnection>, Arc<SyncConnection>) =
@@ -72,12 +327,21 @@ pub async fn setup(tx: mpsc::Sender<Update>, debug: bool) {
         }),
     )));
 
-    let dbus_data = DbusData { tx };
+    let dbus_data = DbusData { tx, accounts };
     let status_token = register_de_nlih_divera_status1_status(&mut cr);
     let monitor_token = register_de_nlih_divera_status1_monitor(&mut cr);
+    let news_token = register_de_nlih_divera_status1_news(&mut cr);
+    let event_token = register_de_nlih_divera_status1_event(&mut cr);
+    let vehicle_token = register_de_nlih_divera_status1_vehicle(&mut cr);
     cr.insert(
         "/de/nlih/DiveraStatus1",
-        &[status_token, monitor_token],
+        &[
+            status_token,
+            monitor_token,
+            news_token,
+            event_token,
+            vehicle_token,
+        ],
         dbus_data,
     );
 
@@ -89,3 +353,171 @@ pub async fn setup(tx: mpsc::Sender<Update>, debug: bool) {
         }),
     );
 }
+
+#[cfg(test)]
+mod test {
+    use crate::dbus_interface::{idle_event, network_update, setup, setup_system, sleep_update};
+    use crate::rules::SessionEvent;
+    use crate::types::Update;
+
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    use dbus::arg::{PropMap, Variant};
+    use dbus::blocking::Connection;
+    use dbus::channel::Sender;
+    use dbus::Message;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    #[test]
+    fn test_signal_updates() {
+        assert_eq!(sleep_update((true,)), None);
+        assert_eq!(sleep_update((false,)), Some(Update::Reload));
+        assert_eq!(network_update((20,)), None);
+        assert_eq!(network_update((70,)), Some(Update::Reload));
+
+        let mut changed = PropMap::new();
+        changed.insert("IdleHint".to_string(), Variant(Box::new(true)));
+        assert_eq!(
+            idle_event((
+                "org.freedesktop.login1.Session".to_string(),
+                changed,
+                vec![]
+            )),
+            Some(SessionEvent::Idle)
+        );
+        assert_eq!(
+            idle_event((
+                "org.freedesktop.login1.User".to_string(),
+                PropMap::new(),
+                vec![]
+            )),
+            None
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_private_bus() {
+        // private bus in place of the session and system bus, libdbus reads the addresses only once
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("dbus-daemon is needed for the dbus tests");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", address.trim());
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", address.trim());
+
+        let (tx, mut rx) = mpsc::channel(8);
+        setup_system(tx, vec![]).await;
+
+        // signals from other senders than logind and NetworkManager are ignored
+        let stranger = Connection::new_system().unwrap();
+        stranger
+            .send(
+                Message::new_signal(
+                    "/org/freedesktop/login1",
+                    "org.freedesktop.login1.Manager",
+                    "PrepareForSleep",
+                )
+                .unwrap()
+                .append1(false),
+            )
+            .unwrap();
+
+        let sender = Connection::new_system().unwrap();
+        for name in ["org.freedesktop.login1", "org.freedesktop.NetworkManager"] {
+            sender.request_name(name, false, true, true).unwrap();
+        }
+        for (path, interface, member, suspend) in [
+            (
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+                "PrepareForSleep",
+                true,
+            ),
+            (
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+                "PrepareForSleep",
+                false,
+            ),
+        ] {
+            sender
+                .send(
+                    Message::new_signal(path, interface, member)
+                        .unwrap()
+                        .append1(suspend),
+                )
+                .unwrap();
+        }
+        sender
+            .send(
+                Message::new_signal(
+                    "/org/freedesktop/NetworkManager",
+                    "org.freedesktop.NetworkManager",
+                    "StateChanged",
+                )
+                .unwrap()
+                .append1(70u32),
+            )
+            .unwrap();
+
+        for _ in 0..2 {
+            let update = timeout(Duration::from_secs(5), rx.recv()).await.unwrap();
+            assert_eq!(update, Some(Update::Reload));
+        }
+        assert!(timeout(Duration::from_millis(200), rx.recv())
+            .await
+            .is_err());
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let statuses = vec![
+            (1, "available".to_string(), "0f0".to_string()),
+            (2, "not available".to_string(), "f00".to_string()),
+        ];
+        setup(tx, vec![("fw".to_string(), statuses.clone())]).await;
+
+        let listed = tokio::task::spawn_blocking(|| {
+            let conn = Connection::new_session().unwrap();
+            let proxy = conn.with_proxy(
+                "de.nlih.diverastatus",
+                "/de/nlih/DiveraStatus1",
+                Duration::from_secs(5),
+            );
+            let status = "de.nlih.DiveraStatus1.Status";
+            let (listed,): (Vec<(u32, String, String)>,) =
+                proxy.method_call(status, "ListStatuses", ()).unwrap();
+            let (account_listed,): (Vec<(u32, String, String)>,) = proxy
+                .method_call(status, "ListStatusesAccount", ("fw",))
+                .unwrap();
+            assert_eq!(account_listed, listed);
+            assert!(proxy
+                .method_call::<(Vec<(u32, String, String)>,), _, _, _>(
+                    status,
+                    "ListStatusesAccount",
+                    ("thw",)
+                )
+                .is_err());
+            proxy
+                .method_call::<(), _, _, _>(status, "Set", ("2",))
+                .unwrap();
+            listed
+        })
+        .await
+        .unwrap();
+        assert_eq!(listed, statuses);
+        assert_eq!(
+            timeout(Duration::from_secs(5), rx.recv()).await.unwrap(),
+            Some(Update::SetStatus(None, "2".to_string(), "remote"))
+        );
+
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+    }
+}
@@ -21,6 +21,12 @@ where T: DeNlihDiveraStatus1Monitor + Send + 'static
 pub trait DeNlihDiveraStatus1Status {
     fn next(&mut self) -> Result<(), dbus::MethodErr>;
     fn previous(&mut self) -> Result<(), dbus::MethodErr>;
+    fn next_account(&mut self, account: String) -> Result<(), dbus::MethodErr>;
+    fn previous_account(&mut self, account: String) -> Result<(), dbus::MethodErr>;
+    fn set(&mut self, status: String) -> Result<(), dbus::MethodErr>;
+    fn set_account(&mut self, account: String, status: String) -> Result<(), dbus::MethodErr>;
+    fn list_statuses(&mut self) -> Result<Vec<(u32, String, String,)>, dbus::MethodErr>;
+    fn list_statuses_account(&mut self, account: String) -> Result<Vec<(u32, String, String,)>, dbus::MethodErr>;
 }
 
 pub fn register_de_nlih_divera_status1_status<T>(cr: &mut crossroads::Crossroads) -> crossroads::IfaceToken<T>
@@ -33,5 +39,67 @@ where T: DeNlihDiveraStatus1Status + Send + 'static
         b.method("Previous", (), (), |_, t: &mut T, ()| {
             t.previous()
         });
+        b.method("NextAccount", ("account",), (), |_, t: &mut T, (account,)| {
+            t.next_account(account,)
+        });
+        b.method("PreviousAccount", ("account",), (), |_, t: &mut T, (account,)| {
+            t.previous_account(account,)
+        });
+        b.method("Set", ("status",), (), |_, t: &mut T, (status,)| {
+            t.set(status,)
+        });
+        b.method("SetAccount", ("account", "status",), (), |_, t: &mut T, (account, status,)| {
+            t.set_account(account, status,)
+        });
+        b.method("ListStatuses", (), ("statuses",), |_, t: &mut T, ()| {
+            t.list_statuses()
+                .map(|x| (x,))
+        });
+        b.method("ListStatusesAccount", ("account",), ("statuses",), |_, t: &mut T, (account,)| {
+            t.list_statuses_account(account,)
+                .map(|x| (x,))
+        });
+    })
+}
+
+pub trait DeNlihDiveraStatus1News {
+    fn mark_all_read(&mut self) -> Result<(), dbus::MethodErr>;
+}
+
+pub fn register_de_nlih_divera_status1_news<T>(cr: &mut crossroads::Crossroads) -> crossroads::IfaceToken<T>
+where T: DeNlihDiveraStatus1News + Send + 'static
+{
+    cr.register("de.nlih.DiveraStatus1.News", |b| {
+        b.method("MarkAllRead", (), (), |_, t: &mut T, ()| {
+            t.mark_all_read()
+        });
+    })
+}
+
+pub trait DeNlihDiveraStatus1Event {
+    fn respond(&mut self, id: u32, accept: bool) -> Result<(), dbus::MethodErr>;
+}
+
+pub fn register_de_nlih_divera_status1_event<T>(cr: &mut crossroads::Crossroads) -> crossroads::IfaceToken<T>
+where T: DeNlihDiveraStatus1Event + Send + 'static
+{
+    cr.register("de.nlih.DiveraStatus1.Event", |b| {
+        b.method("Respond", ("id", "accept", ), (), |_, t: &mut T, (id, accept, )| {
+            t.respond(id, accept, )
+        });
+    })
+}
+
+pub trait DeNlihDiveraStatus1Vehicle {
+    fn set_status(&mut self, id: u32, fms: u8) -> Result<(), dbus::MethodErr>;
+}
+
+pub fn register_de_nlih_divera_status1_vehicle<T>(cr: &mut crossroads::Crossroads) -> crossroads::IfaceToken<T>
+where T: DeNlihDiveraStatus1Vehicle + Send + 'static
+{
+    cr.register("de.nlih.DiveraStatus1.Vehicle", |b| {
+        b.method("SetStatus", ("id", "fms", ), (), |_, t: &mut T, (id, fms, )| {
+            t.set_status(id, fms, )
+        });
     })
 }
@@ -0,0 +1,73 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum Error {
+    Request(reqwest::Error),
+    UnknownStatus(String),
+    AmbiguousStatus(String, Vec<u32>),
+    InvalidConfig(Vec<String>),
+    LoginFailed(String),
+    Io(std::io::Error),
+    #[cfg(feature = "history")]
+    History(rusqlite::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Request(err) => write!(f, "request to divera failed: {}", err),
+            Error::UnknownStatus(status) => write!(f, "unknown status: {}", status),
+            Error::AmbiguousStatus(status, ids) => write!(
+                f,
+                "status {} is ambiguous, use one of the ids {}",
+                status,
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Error::InvalidConfig(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+            Error::LoginFailed(reason) => write!(f, "login failed: {}", reason),
+            Error::Io(err) => write!(f, "{}", err),
+            #[cfg(feature = "history")]
+            Error::History(err) => write!(f, "history database: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Request(err) => Some(err),
+            Error::Io(err) => Some(err),
+            #[cfg(feature = "history")]
+            Error::History(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Request(err)
+    }
+}
+
+#[cfg(feature = "history")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::History(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,315 @@
+#![cfg(feature = "history")]
+
+use crate::{api, AccountState};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{Datelike, TimeZone};
+use log::debug;
+use rusqlite::{params, Connection, OptionalExtension};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS poll (
+    id INTEGER PRIMARY KEY,
+    time INTEGER NOT NULL,
+    account TEXT NOT NULL,
+    available INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshot (
+    poll INTEGER NOT NULL REFERENCES poll(id),
+    status_id INTEGER NOT NULL,
+    members INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS own_status (
+    time INTEGER NOT NULL,
+    account TEXT NOT NULL,
+    status_id INTEGER NOT NULL,
+    status_name TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS poll_account_time ON poll(account, time);
+CREATE INDEX IF NOT EXISTS own_status_account_time ON own_status(account, time);
+";
+
+/// Monitor snapshots and own status changes of every update.
+pub struct History {
+    db: Connection,
+    /// last recorded own status of the accounts
+    last_status: HashMap<String, u32>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self, rusqlite::Error> {
+        debug!("opening history {}", path.display());
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    fn with_connection(db: Connection) -> Result<Self, rusqlite::Error> {
+        db.execute_batch(SCHEMA)?;
+        Ok(History {
+            db,
+            last_status: HashMap::new(),
+        })
+    }
+
+    /// Store the monitor of an update and the own status if it changed since the last one.
+    pub(crate) fn record(
+        &mut self,
+        state: &AccountState,
+        time: i64,
+    ) -> Result<(), rusqlite::Error> {
+        let account = state.account.name.as_str();
+        let basic = state.data.monitor().basic();
+        let available: u32 = state
+            .resolved
+            .available_statuses
+            .iter()
+            .filter_map(|id| basic.get(&id.to_string()))
+            .map(|status| status.count())
+            .sum();
+
+        let transaction = self.db.transaction()?;
+        transaction.execute(
+            "INSERT INTO poll (time, account, available) VALUES (?1, ?2, ?3)",
+            params![time, account, available],
+        )?;
+        let poll = transaction.last_insert_rowid();
+        for (id, status) in basic {
+            transaction.execute(
+                "INSERT INTO snapshot (poll, status_id, members) VALUES (?1, ?2, ?3)",
+                params![poll, id.parse::<u32>().unwrap_or_default(), status.count()],
+            )?;
+        }
+
+        let status_id = *state.data.status().status_id();
+        let last_status = match self.last_status.get(account) {
+            Some(id) => Some(*id),
+            None => transaction
+                .query_row(
+                    "SELECT status_id FROM own_status WHERE account = ?1 ORDER BY time DESC LIMIT 1",
+                    params![account],
+                    |row| row.get(0),
+                )
+                .optional()?,
+        };
+        if last_status != Some(status_id) {
+            let name = state
+                .status_map
+                .get(&status_id.to_string())
+                .map(|status| status.name().as_str())
+                .unwrap_or_default();
+            debug!("recording status {} ({})", status_id, name);
+            transaction.execute(
+                "INSERT INTO own_status (time, account, status_id, status_name) VALUES (?1, ?2, ?3, ?4)",
+                params![time, account, status_id, name],
+            )?;
+        }
+        transaction.commit()?;
+
+        self.last_status.insert(account.to_string(), status_id);
+        Ok(())
+    }
+
+    /// Average available members by local weekday (0 is sunday) and hour.
+    pub fn availability(&self, account: &str) -> Result<Vec<(u32, u32, f64)>, rusqlite::Error> {
+        let mut statement = self.db.prepare(
+            "SELECT CAST(strftime('%w', time, 'unixepoch', 'localtime') AS INTEGER) AS weekday,
+                CAST(strftime('%H', time, 'unixepoch', 'localtime') AS INTEGER) AS hour,
+                avg(available)
+            FROM poll WHERE account = ?1 GROUP BY weekday, hour",
+        )?;
+        let rows = statement.query_map(params![account], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect()
+    }
+
+    /// Times between `from` and `until` with polls at most `max_gap` seconds apart.
+    ///
+    /// Longer gaps mean that the daemon was not running or could not reach the server.
+    fn polled_ranges(
+        &self,
+        account: &str,
+        from: i64,
+        until: i64,
+        max_gap: i64,
+    ) -> Result<Vec<(i64, i64)>, rusqlite::Error> {
+        let mut statement = self.db.prepare(
+            "SELECT time FROM poll WHERE account = ?1 AND time < ?3 AND time >= coalesce(
+                (SELECT max(time) FROM poll WHERE account = ?1 AND time <= ?2), ?2)
+            ORDER BY time",
+        )?;
+        let mut polls = statement
+            .query_map(params![account, from, until], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        polls.push(until);
+
+        Ok(polls
+            .windows(2)
+            .filter(|pair| pair[1] - pair[0] <= max_gap)
+            .map(|pair| (pair[0].max(from), pair[1]))
+            .collect())
+    }
+
+    /// Minutes spent in each own status between `from` and `until`, the longest first.
+    ///
+    /// Gaps of more than `max_gap` seconds between the polls count as "unknown".
+    pub fn status_minutes(
+        &self,
+        account: &str,
+        from: i64,
+        until: i64,
+        max_gap: i64,
+    ) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+        // the status at `from` was set by the last change before it
+        let mut statement = self.db.prepare(
+            "SELECT time, status_name FROM own_status WHERE account = ?1 AND time < ?3 AND time >= coalesce(
+                (SELECT max(time) FROM own_status WHERE account = ?1 AND time <= ?2), ?2)
+            ORDER BY time",
+        )?;
+        let changes = statement
+            .query_map(params![account, from, until], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let polled = self.polled_ranges(account, from, until, max_gap)?;
+
+        let mut seconds: Vec<(String, i64)> = Vec::new();
+        let mut add =
+            |name: &str, duration: i64| match seconds.iter_mut().find(|(other, _)| other == name) {
+                Some((_, total)) => *total += duration,
+                None => seconds.push((name.to_string(), duration)),
+            };
+        for (index, (time, name)) in changes.iter().enumerate() {
+            let start = (*time).max(from);
+            let end = changes.get(index + 1).map_or(until, |(time, _)| *time);
+            let known: i64 = polled
+                .iter()
+                .map(|(poll_start, poll_end)| (end.min(*poll_end) - start.max(*poll_start)).max(0))
+                .sum();
+            if known > 0 {
+                add(name, known);
+            }
+            if end - start > known {
+                add("unknown", end - start - known);
+            }
+        }
+
+        let mut minutes: Vec<(String, i64)> = seconds
+            .into_iter()
+            .map(|(name, seconds)| (name, seconds / 60))
+            .collect();
+        minutes.sort_by(|(_, a), (_, b)| b.cmp(a));
+        Ok(minutes)
+    }
+}
+
+/// Print the availability by weekday and hour and the minutes in each status this month.
+///
+/// Gaps of more than `max_gap` seconds between the polls are shown as unknown status.
+pub(crate) fn report(
+    history: &History,
+    account: &str,
+    max_gap: i64,
+) -> Result<(), rusqlite::Error> {
+    let availability = history.availability(account)?;
+
+    println!(
+        "   {}",
+        (0..24)
+            .map(|hour| format!("{:>4}", hour))
+            .collect::<String>()
+    );
+    for (weekday, name) in [
+        (1, "Mon"),
+        (2, "Tue"),
+        (3, "Wed"),
+        (4, "Thu"),
+        (5, "Fri"),
+        (6, "Sat"),
+        (0, "Sun"),
+    ] {
+        let hours: String = (0..24)
+            .map(|hour| {
+                match availability
+                    .iter()
+                    .find(|(day, other, _)| *day == weekday && *other == hour)
+                {
+                    Some((_, _, average)) => format!("{:>4.1}", average),
+                    None => "   -".to_string(),
+                }
+            })
+            .collect();
+        println!("{}{}", name, hours);
+    }
+
+    let month_start = chrono::Local::now()
+        .date_naive()
+        .with_day(1)
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .and_then(|time| chrono::Local.from_local_datetime(&time).earliest())
+        .map_or(0, |time| time.timestamp());
+    println!();
+    for (name, minutes) in history.status_minutes(account, month_start, api::unix_now(), max_gap)? {
+        println!("{}\t{}", minutes, name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::History;
+
+    use rusqlite::{params, Connection};
+
+    #[test]
+    fn test_status_minutes() {
+        let history = History::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        for (time, name) in [
+            (0, "off duty"),
+            (600, "available"),
+            (1200, "off duty"),
+            (3000, "available"),
+        ] {
+            history
+                .db
+                .execute(
+                    "INSERT INTO own_status (time, account, status_id, status_name) VALUES (?1, '', 1, ?2)",
+                    params![time, name],
+                )
+                .unwrap();
+        }
+
+        // not running between 1200 and 2400 and after 3600
+        for time in (0..=1200).step_by(60).chain((2400..=3600).step_by(60)) {
+            history
+                .db
+                .execute(
+                    "INSERT INTO poll (time, account, available) VALUES (?1, '', 0)",
+                    params![time],
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            history.status_minutes("", 300, 1200, 180).unwrap(),
+            vec![("available".to_string(), 10), ("off duty".to_string(), 5)]
+        );
+        assert_eq!(
+            history.status_minutes("", 300, 3600, 180).unwrap(),
+            vec![
+                ("available".to_string(), 20),
+                ("unknown".to_string(), 20),
+                ("off duty".to_string(), 15)
+            ]
+        );
+        assert_eq!(
+            history.status_minutes("", 4000, 4600, 180).unwrap(),
+            vec![("unknown".to_string(), 10)]
+        );
+        assert!(history
+            .status_minutes("other", 0, 600, 180)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+#![cfg(any(feature = "metrics", feature = "rest-api"))]
+
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+const MAX_HEAD: usize = 8192;
+const MAX_BODY: usize = 65536;
+/// time a client has to send the whole request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A minimal http/1.1 request, enough for local clients.
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Value of the header, the name is case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: String) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Self::new(status, "text/plain", String::new())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the head and the body with a Content-Length, fails if the client is too slow.
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Request, io::Error> {
+    read_request_within(stream, READ_TIMEOUT).await
+}
+
+async fn read_request_within<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    limit: Duration,
+) -> Result<Request, io::Error> {
+    timeout(limit, read_request_data(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out reading the request"))?
+}
+
+async fn read_request_data<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Request, io::Error> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+    let head_end = loop {
+        if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        if data.len() > MAX_HEAD {
+            return Err(invalid("request head too long"));
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(invalid("incomplete request"));
+        }
+        data.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let mut request = Request {
+        method: request_line.next().unwrap_or_default().to_string(),
+        path: request_line.next().unwrap_or_default().to_string(),
+        ..Default::default()
+    };
+    request.headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let length = match request.header("Content-Length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| invalid("invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(invalid("request body too long"));
+    }
+    request.body = data[head_end + 4..].to_vec();
+    while request.body.len() < length {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(invalid("incomplete request body"));
+        }
+        request.body.extend_from_slice(&buffer[..read]);
+    }
+    request.body.truncate(length);
+
+    Ok(request)
+}
+
+/// Write the response and close the connection.
+pub async fn write_response<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    response: &Response,
+) -> Result<(), io::Error> {
+    let data = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(data.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_request, read_request_within};
+
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            client
+                .write_all(b"POST /status HTTP/1.1\r\nauthorization: Bearer abc\r\nContent-Length: 15\r\n\r\n{\"status\": \"3\"}")
+                .await
+                .unwrap();
+        });
+
+        let request = read_request(&mut server).await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/status");
+        assert_eq!(request.header("Authorization"), Some("Bearer abc"));
+        assert_eq!(request.body, b"{\"status\": \"3\"}");
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(b"GET /state HTTP/1.1\r\n").await.unwrap();
+
+        let err = read_request_within(&mut server, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
@@ -1,43 +1,284 @@
 #![cfg(feature = "i3blocks")]
+use crate::config::{Account, ClickAction, ClickBinding};
+use crate::error::Error;
 use crate::types::Update;
 
 use std::io::stdin;
+use std::process::{Command, Stdio};
+use std::sync::mpsc as std_mpsc;
+use std::sync::mpsc::RecvTimeoutError;
 use std::thread;
+use std::time::Duration;
 
+use log::{debug, trace, warn};
 use serde::Deserialize;
 use tokio::sync::mpsc;
 
+/// Longest time between the two clicks of a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Caps lock and num lock, ignored when matching modifiers.
+const IGNORED_MODIFIERS: [&str; 2] = ["Lock", "Mod2"];
+
 #[derive(Debug, Deserialize)]
 struct ClickEvent {
     button: u32,
+    #[serde(default)]
+    modifiers: Vec<String>,
 }
 
-fn read_stdin(tx: mpsc::Sender<Update>, debug: bool) {
-    let stdin = stdin();
-    if debug {
-        println!("debug: reading on stdin");
+/// Sorted lowercase modifiers without the ignored ones.
+fn held(modifiers: &[String]) -> Vec<String> {
+    let mut held: Vec<String> = modifiers
+        .iter()
+        .filter(|modifier| !IGNORED_MODIFIERS.contains(&modifier.as_str()))
+        .map(|modifier| modifier.to_lowercase())
+        .collect();
+    held.sort();
+    held.dedup();
+    held
+}
+
+impl ClickEvent {
+    fn same_as(&self, other: &ClickEvent) -> bool {
+        self.button == other.button && held(&self.modifiers) == held(&other.modifiers)
+    }
+
+    fn matches(&self, binding: &ClickBinding) -> bool {
+        self.button == binding.button && held(&self.modifiers) == held(&binding.modifiers)
+    }
+}
+
+/// Button 4 (mousewheel up) sets the next status, 5 (down) the previous one and all others reload.
+fn default_update(button: u32) -> Update {
+    match button {
+        4 => Update::StatusNext(None),
+        5 => Update::StatusPrev(None),
+        _ => Update::Reload,
+    }
+}
+
+/// Run the command without blocking the caller.
+fn spawn(mut command: Command) {
+    command.stdin(Stdio::null()).stdout(Stdio::null());
+    thread::spawn(move || match command.status() {
+        Ok(status) if !status.success() => warn!("{:?} failed with {}", command, status),
+        Ok(_) => {}
+        Err(err) => warn!("could not run {:?}: {}", command, err),
+    });
+}
+
+/// Configured click actions and the servers of the accounts for `open`.
+struct Clicks {
+    bindings: Vec<ClickBinding>,
+    servers: Vec<(String, String)>,
+}
+
+impl Clicks {
+    /// Check that every action has what it needs.
+    fn new(bindings: Vec<ClickBinding>, accounts: &[Account]) -> Result<Self, Error> {
+        let mut problems: Vec<String> = Vec::new();
+        for binding in &bindings {
+            match binding.action {
+                ClickAction::Set if binding.status.is_none() => {
+                    problems.push(format!("click: button {} sets no status", binding.button))
+                }
+                ClickAction::Run if binding.command.is_none() => {
+                    problems.push(format!("click: button {} runs no command", binding.button))
+                }
+                _ => {}
+            }
+            if let Some(account) = &binding.account {
+                if !accounts.iter().any(|known| known.name == *account) {
+                    problems.push(format!("click: unknown account {}", account));
+                }
+            }
+        }
+        if !problems.is_empty() {
+            return Err(Error::InvalidConfig(problems));
+        }
+
+        Ok(Clicks {
+            bindings,
+            servers: accounts
+                .iter()
+                .map(|account| (account.name.clone(), account.server.clone()))
+                .collect(),
+        })
     }
+
+    /// Whether a click has to wait for a possible second one.
+    fn waits_for_double(&self, event: &ClickEvent) -> bool {
+        self.bindings
+            .iter()
+            .any(|binding| binding.double && event.matches(binding))
+    }
+
+    /// Update for the daemon, `None` for actions handled here.
+    fn update(&self, event: &ClickEvent, double: bool) -> Option<Update> {
+        let Some(binding) = self
+            .bindings
+            .iter()
+            .find(|binding| binding.double == double && event.matches(binding))
+        else {
+            return Some(default_update(event.button));
+        };
+
+        let account = binding.account.clone();
+        match binding.action {
+            ClickAction::Reload => Some(Update::Reload),
+            ClickAction::Next => Some(Update::StatusNext(account)),
+            ClickAction::Previous => Some(Update::StatusPrev(account)),
+            ClickAction::Set => Some(Update::SetStatus(
+                account,
+                binding.status.clone().unwrap_or_default(),
+                "click",
+            )),
+            ClickAction::Privacy => Some(Update::TogglePrivacy),
+            ClickAction::Open => {
+                let server = match &account {
+                    Some(account) => self.servers.iter().find(|(name, _)| name == account),
+                    None => self.servers.first(),
+                };
+                if let Some((_, server)) = server {
+                    debug!("opening {}", server);
+                    let mut command = Command::new("xdg-open");
+                    command.arg(server);
+                    spawn(command);
+                }
+                None
+            }
+            ClickAction::Run => {
+                let mut command = Command::new("sh");
+                command
+                    .arg("-c")
+                    .arg(binding.command.as_deref().unwrap_or_default());
+                spawn(command);
+                None
+            }
+        }
+    }
+}
+
+fn read_stdin(tx: std_mpsc::Sender<ClickEvent>) {
+    let stdin = stdin();
+    debug!("reading on stdin");
     loop {
         let mut buffer = String::new();
         let _ = stdin.read_line(&mut buffer);
-        if debug {
-            println!("debug: got input on stdin: '{}'", buffer);
-        }
+        trace!("got input on stdin: '{}'", buffer);
         let event: ClickEvent =
             serde_json::from_str(buffer.as_str()).expect("got invalid json as click event");
 
-        let to_send = match event.button {
-            // mousewheel up
-            4 => Update::StatusNext,
-            // mousewheel down
-            5 => Update::StatusPrev,
-            _ => Update::Reload,
+        tx.send(event).expect("click handler stopped");
+    }
+}
+
+/// Turn the clicks into updates, a click with a double click action waits for the second one.
+fn handle_clicks(clicks: Clicks, events: std_mpsc::Receiver<ClickEvent>, tx: mpsc::Sender<Update>) {
+    let send = |event: &ClickEvent, double: bool| {
+        if let Some(update) = clicks.update(event, double) {
+            tx.blocking_send(update)
+                .expect("channel closed by main thread");
+        }
+    };
+
+    let mut waiting: Option<ClickEvent> = None;
+    loop {
+        let event = match waiting.take() {
+            None => events.recv().expect("stdin reader stopped"),
+            Some(first) => match events.recv_timeout(DOUBLE_CLICK) {
+                Ok(second) if second.same_as(&first) => {
+                    send(&first, true);
+                    continue;
+                }
+                Ok(other) => {
+                    send(&first, false);
+                    other
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    send(&first, false);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => panic!("stdin reader stopped"),
+            },
         };
-        tx.blocking_send(to_send)
-            .expect("channel closed by main thread");
+
+        if clicks.waits_for_double(&event) {
+            waiting = Some(event);
+        } else {
+            send(&event, false);
+        }
     }
 }
 
-pub fn setup(tx: mpsc::Sender<Update>, debug: bool) {
-    thread::spawn(move || read_stdin(tx, debug));
+pub fn setup(
+    tx: mpsc::Sender<Update>,
+    bindings: Vec<ClickBinding>,
+    accounts: &[Account],
+) -> Result<(), Error> {
+    let clicks = Clicks::new(bindings, accounts)?;
+    let (events_tx, events) = std_mpsc::channel();
+    thread::spawn(move || read_stdin(events_tx));
+    thread::spawn(move || handle_clicks(clicks, events, tx));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClickEvent, Clicks};
+    use crate::config::{ClickAction, ClickBinding};
+    use crate::types::Update;
+
+    fn binding(button: u32, modifiers: &[&str], double: bool, action: ClickAction) -> ClickBinding {
+        ClickBinding {
+            button,
+            modifiers: modifiers
+                .iter()
+                .map(|modifier| modifier.to_string())
+                .collect(),
+            double,
+            action,
+            account: None,
+            status: None,
+            command: None,
+        }
+    }
+
+    fn click(json: &str) -> ClickEvent {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_clicks() {
+        let mut set = binding(1, &["Shift"], false, ClickAction::Set);
+        set.status = Some("available".to_string());
+        let clicks =
+            Clicks::new(vec![set, binding(1, &[], true, ClickAction::Privacy)], &[]).unwrap();
+
+        // caps lock does not matter
+        let shift_click = click(r#"{"button": 1, "modifiers": ["Lock", "Shift"], "x": 10}"#);
+        assert_eq!(
+            clicks.update(&shift_click, false),
+            Some(Update::SetStatus(None, "available".to_string(), "click"))
+        );
+        assert!(!clicks.waits_for_double(&shift_click));
+
+        let left_click = click(r#"{"button": 1}"#);
+        assert!(clicks.waits_for_double(&left_click));
+        assert!(!left_click.same_as(&shift_click));
+        assert_eq!(
+            clicks.update(&left_click, true),
+            Some(Update::TogglePrivacy)
+        );
+        assert_eq!(clicks.update(&left_click, false), Some(Update::Reload));
+        assert_eq!(
+            clicks.update(&click(r#"{"button": 5}"#), false),
+            Some(Update::StatusPrev(None))
+        );
+
+        let mut unknown = binding(2, &[], false, ClickAction::Next);
+        unknown.account = Some("thw".to_string());
+        assert!(Clicks::new(vec![unknown, binding(3, &[], false, ClickAction::Run)], &[]).is_err());
+    }
 }
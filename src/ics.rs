@@ -0,0 +1,558 @@
+use crate::api_types::Event;
+use crate::config::Account;
+use crate::rules;
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use log::warn;
+
+/// Event read from a calendar file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    pub summary: String,
+    pub categories: Vec<String>,
+    /// unix timestamp
+    pub start: i64,
+    /// unix timestamp
+    pub end: i64,
+    pub recurrence: Option<Recurrence>,
+    /// unix timestamps of the occurrences removed by EXDATE or replaced by an event with a RECURRENCE-ID
+    pub exceptions: Vec<i64>,
+}
+
+/// The supported part of an RRULE: FREQ=DAILY or FREQ=WEEKLY with INTERVAL, COUNT, UNTIL, BYDAY and WKST.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    /// days between two occurrences, or between two weeks with occurrences
+    pub days: i64,
+    /// number of occurrences including the first one
+    pub count: Option<i64>,
+    /// unix timestamp of the last possible start
+    pub until: Option<i64>,
+    /// days of the week with an occurrence, empty for the day of the first one
+    pub weekdays: Vec<Weekday>,
+    /// first day of the week
+    pub week_start: Weekday,
+}
+
+fn weekday(value: &str) -> Option<Weekday> {
+    match value.to_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Days from `from` to the next `to`, 0 for the same day.
+fn days_between(from: Weekday, to: Weekday) -> i64 {
+    (to.num_days_from_monday() as i64 - from.num_days_from_monday() as i64).rem_euclid(7)
+}
+
+impl Recurrence {
+    /// Parse the value of an RRULE, None for rules that are not supported.
+    fn parse(value: &str) -> Option<Self> {
+        let mut recurrence = Recurrence {
+            days: 0,
+            count: None,
+            until: None,
+            weekdays: Vec::new(),
+            week_start: Weekday::Mon,
+        };
+        let mut interval = 1;
+
+        for part in value.split(';') {
+            let (name, value) = part.split_once('=')?;
+            match name.to_uppercase().as_str() {
+                "FREQ" => {
+                    recurrence.days = match value.to_uppercase().as_str() {
+                        "DAILY" => 1,
+                        "WEEKLY" => 7,
+                        _ => return None,
+                    }
+                }
+                "INTERVAL" => interval = value.parse::<i64>().ok().filter(|i| *i > 0)?,
+                "COUNT" => recurrence.count = Some(value.parse().ok()?),
+                "UNTIL" if value.len() == 8 => {
+                    // the whole day is included
+                    recurrence.until = Some(parse_time("VALUE=DATE", value)? + 24 * 3600 - 1);
+                }
+                "UNTIL" => recurrence.until = Some(parse_time("", value)?),
+                "BYDAY" => {
+                    recurrence.weekdays = value.split(',').map(weekday).collect::<Option<_>>()?
+                }
+                "WKST" => recurrence.week_start = weekday(value)?,
+                _ => return None,
+            }
+        }
+
+        // BYDAY only adds occurrences to weekly rules
+        if recurrence.days != 7 && !recurrence.weekdays.is_empty() {
+            return None;
+        }
+        recurrence.days *= interval;
+        (recurrence.days > 0).then_some(recurrence)
+    }
+}
+
+impl CalendarEvent {
+    /// Starts and ends of the occurrences that end after `from` and start until `until`.
+    pub fn occurrences(&self, from: i64, until: i64) -> Vec<(i64, i64)> {
+        let duration = self.end - self.start;
+        let Some(recurrence) = &self.recurrence else {
+            return vec![(self.start, self.end)];
+        };
+        let first_start = rules::local_time(self.start);
+
+        // days of the occurrences in a period, relative to the first start
+        let mut offsets: Vec<i64> = if recurrence.weekdays.is_empty() {
+            vec![0]
+        } else {
+            let start_offset = days_between(recurrence.week_start, first_start.weekday());
+            recurrence
+                .weekdays
+                .iter()
+                .map(|day| days_between(recurrence.week_start, *day) - start_offset)
+                .collect()
+        };
+        offsets.sort();
+        offsets.dedup();
+
+        // skip the periods that ended long ago, unless they are needed for the count,
+        // one period and one day of slack for the weekdays and daylight saving changes
+        let period = recurrence.days * 24 * 3600;
+        let mut index = if recurrence.count.is_some() {
+            0
+        } else {
+            ((from - self.end - 24 * 3600) / period - 1).max(0)
+        };
+
+        let mut occurrences = Vec::new();
+        let mut number = 0;
+        loop {
+            for offset in &offsets {
+                let day = index * recurrence.days + offset;
+                if day < 0 {
+                    continue;
+                }
+                if recurrence.count.is_some_and(|count| number >= count) {
+                    return occurrences;
+                }
+                number += 1;
+
+                let start = rules::unix_time(first_start + chrono::Duration::days(day));
+                if start > until || recurrence.until.is_some_and(|last| start > last) {
+                    return occurrences;
+                }
+                if start + duration > from && !self.exceptions.contains(&start) {
+                    occurrences.push((start, start + duration));
+                }
+            }
+            index += 1;
+        }
+    }
+}
+
+/// Escape a text value as described in RFC 5545 section 3.3.11.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+/// Split a list value at commas that are not escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if c == ',' && !escaped {
+            items.push(unescape(&item));
+            item.clear();
+        } else {
+            item.push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    items.push(unescape(&item));
+    items
+}
+
+/// Join continuation lines, which start with a space or tab.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Unix time of a DATE or DATE-TIME value, times with a TZID are taken as local time.
+fn parse_time(params: &str, value: &str) -> Option<i64> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Utc.from_utc_datetime(&time).timestamp());
+    }
+
+    let time = if params.contains("VALUE=DATE") && !params.contains("VALUE=DATE-TIME") {
+        NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?
+    };
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+/// Read the events of a calendar, with daily and weekly recurrence rules.
+///
+/// Events with a RECURRENCE-ID replace that occurrence of the recurring event with the same UID.
+/// Properties of components inside an event like VALARM are ignored.
+pub fn parse(content: &str) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    // UID of each event in `events`
+    let mut uids: Vec<String> = Vec::new();
+    // UID and RECURRENCE-ID of the events replacing an occurrence
+    let mut replacements: Vec<(String, i64)> = Vec::new();
+    let mut current: Option<CalendarEvent> = None;
+    let mut all_day = false;
+    let mut rrule: Option<String> = None;
+    let mut uid = String::new();
+    let mut recurrence_id: Option<i64> = None;
+    // depth of components nested in the current event
+    let mut nested = 0;
+
+    for line in unfold(content) {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+
+        match (name.to_uppercase().as_str(), current.as_mut()) {
+            ("BEGIN", None) if value == "VEVENT" => {
+                current = Some(CalendarEvent {
+                    summary: String::new(),
+                    categories: Vec::new(),
+                    start: 0,
+                    end: 0,
+                    recurrence: None,
+                    exceptions: Vec::new(),
+                });
+                all_day = false;
+                rrule = None;
+                uid.clear();
+                recurrence_id = None;
+                nested = 0;
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            (_, Some(_)) if nested > 0 => {}
+            ("END", Some(_)) if value == "VEVENT" => {
+                let mut event = current.take().expect("checked in the match");
+                if event.end <= event.start {
+                    event.end = event.start + if all_day { 24 * 3600 } else { 0 };
+                }
+                if let Some(recurrence_id) = recurrence_id {
+                    // a single replaced occurrence
+                    replacements.push((uid.clone(), recurrence_id));
+                } else if let Some(rrule) = rrule.take() {
+                    event.recurrence = Recurrence::parse(&rrule);
+                    if event.recurrence.is_none() {
+                        warn!(
+                            "recurrence {} of calendar event {} is not supported, only the first occurrence is used",
+                            rrule, event.summary
+                        );
+                    }
+                }
+                if event.start > 0 {
+                    events.push(event);
+                    uids.push(uid.clone());
+                }
+            }
+            ("SUMMARY", Some(event)) => event.summary = unescape(value),
+            ("CATEGORIES", Some(event)) => event.categories.extend(split_list(value)),
+            ("DTSTART", Some(event)) => {
+                all_day = params.contains("VALUE=DATE") && !params.contains("VALUE=DATE-TIME");
+                event.start = parse_time(params, value).unwrap_or_default();
+            }
+            ("DTEND", Some(event)) => event.end = parse_time(params, value).unwrap_or_default(),
+            ("RRULE", Some(_)) => rrule = Some(value.to_string()),
+            ("EXDATE", Some(event)) => event
+                .exceptions
+                .extend(value.split(',').filter_map(|time| parse_time(params, time))),
+            ("UID", Some(_)) => uid = value.to_string(),
+            ("RECURRENCE-ID", Some(_)) => recurrence_id = parse_time(params, value),
+            _ => {}
+        }
+    }
+
+    for (uid, recurrence_id) in replacements {
+        for (event, _) in events
+            .iter_mut()
+            .zip(&uids)
+            .filter(|(event, event_uid)| **event_uid == uid && event.recurrence.is_some())
+        {
+            event.exceptions.push(recurrence_id);
+        }
+    }
+
+    events
+}
+
+/// Split lines longer than 75 octets, continuation lines start with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+fn timestamp(unix: i64) -> String {
+    Utc.timestamp_opt(unix, 0)
+        .single()
+        .expect("invalid timestamp")
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn host(server: &str) -> &str {
+    server
+        .split("://")
+        .last()
+        .unwrap_or(server)
+        .trim_end_matches('/')
+}
+
+/// Calendar with the given events, `now` is used as creation time.
+pub fn export(events: &[(&Account, &Event)], now: i64) -> String {
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//divera-status//events//EN".to_string(),
+    ];
+
+    for (account, event) in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!(
+            "UID:event-{}@{}",
+            event.id(),
+            host(&account.server)
+        ));
+        lines.push(format!("DTSTAMP:{}", timestamp(now)));
+        lines.push(format!("DTSTART:{}", timestamp(*event.start())));
+        lines.push(format!("DTEND:{}", timestamp(*event.end())));
+        lines.push(format!("SUMMARY:{}", escape(event.title())));
+        if !event.text().is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape(event.text())));
+        }
+        if !event.address().is_empty() {
+            lines.push(format!("LOCATION:{}", escape(event.address())));
+        }
+        if !account.name.is_empty() {
+            lines.push(format!("CATEGORIES:{}", escape(&account.name)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line) + "\r\n")
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ics::{escape, fold, parse, split_list, timestamp, unescape, Recurrence};
+    use chrono::Weekday;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(escape("a, b; c\\d\ne"), "a\\, b\\; c\\\\d\\ne");
+        assert_eq!(timestamp(86400 + 3661), "19700102T010101Z");
+
+        let folded = fold(&"ä".repeat(50));
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), 74);
+        assert!(lines[1].starts_with(' '));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(unescape("a\\, b\\; c\\nd"), "a, b; c\nd");
+        assert_eq!(split_list("a,b\\,c"), vec!["a", "b,c"]);
+
+        let events = parse(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Night\r\n  shift\r\n\
+             CATEGORIES:Duty,Rescue\r\n\
+             DTSTART:19700102T000000Z\r\n\
+             DTEND:19700102T080000Z\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:no start\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n",
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Night shift");
+        assert_eq!(events[0].categories, vec!["Duty", "Rescue"]);
+        assert_eq!(events[0].start, 86400);
+        assert_eq!(events[0].end, 86400 + 8 * 3600);
+        assert_eq!(events[0].recurrence, None);
+    }
+
+    #[test]
+    fn test_parse_nested_and_recurring() {
+        let events = parse(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Day shift\r\n\
+             DTSTART:19700102T060000Z\r\n\
+             DTEND:19700102T180000Z\r\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=4\r\n\
+             BEGIN:VALARM\r\n\
+             ACTION:DISPLAY\r\n\
+             SUMMARY:Reminder\r\n\
+             END:VALARM\r\n\
+             CATEGORIES:Duty\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Meeting\r\n\
+             DTSTART:19700103T060000Z\r\n\
+             RRULE:FREQ=MONTHLY;BYMONTHDAY=3\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n",
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].summary, "Day shift");
+        assert_eq!(events[0].categories, vec!["Duty"]);
+        assert_eq!(
+            events[0].recurrence,
+            Some(Recurrence {
+                days: 14,
+                count: Some(4),
+                until: None,
+                weekdays: vec![],
+                week_start: Weekday::Mon,
+            })
+        );
+        assert_eq!(events[1].summary, "Meeting");
+        assert_eq!(events[1].recurrence, None);
+
+        assert_eq!(
+            Recurrence::parse("FREQ=DAILY;UNTIL=19700105T000000Z"),
+            Some(Recurrence {
+                days: 1,
+                count: None,
+                until: Some(4 * 86400),
+                weekdays: vec![],
+                week_start: Weekday::Mon,
+            })
+        );
+        assert_eq!(Recurrence::parse("FREQ=DAILY;BYDAY=MO"), None);
+        assert_eq!(Recurrence::parse("FREQ=WEEKLY;BYDAY=1MO"), None);
+    }
+
+    #[test]
+    fn test_weekdays() {
+        // Friday 1970-01-02, then every other week on Monday and Friday, five times
+        let events = parse(
+            "BEGIN:VEVENT\r\n\
+             DTSTART:19700102T100000Z\r\n\
+             DTEND:19700102T110000Z\r\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR;COUNT=5\r\n\
+             END:VEVENT\r\n",
+        );
+        let starts: Vec<i64> = events[0]
+            .occurrences(0, 100 * 86400)
+            .into_iter()
+            .map(|(start, _)| (start - 10 * 3600) / 86400)
+            .collect();
+        assert_eq!(starts, vec![1, 11, 15, 25, 29]);
+
+        // only the occurrences still needed
+        let starts: Vec<i64> = events[0]
+            .occurrences(20 * 86400, 100 * 86400)
+            .into_iter()
+            .map(|(start, _)| (start - 10 * 3600) / 86400)
+            .collect();
+        assert_eq!(starts, vec![25, 29]);
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let events = parse(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:shift@example.com\r\n\
+             SUMMARY:Shift\r\n\
+             DTSTART:19700102T060000Z\r\n\
+             DTEND:19700102T180000Z\r\n\
+             RRULE:FREQ=DAILY;COUNT=5\r\n\
+             EXDATE:19700103T060000Z,19700104T060000Z\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:shift@example.com\r\n\
+             RECURRENCE-ID:19700105T060000Z\r\n\
+             SUMMARY:Late shift\r\n\
+             DTSTART:19700105T120000Z\r\n\
+             DTEND:19700105T200000Z\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n",
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].occurrences(0, 100 * 86400),
+            vec![
+                (86400 + 6 * 3600, 86400 + 18 * 3600),
+                (5 * 86400 + 6 * 3600, 5 * 86400 + 18 * 3600)
+            ]
+        );
+        assert_eq!(events[1].summary, "Late shift");
+        assert_eq!(events[1].recurrence, None);
+        assert_eq!(
+            events[1].occurrences(0, 100 * 86400),
+            vec![(4 * 86400 + 12 * 3600, 4 * 86400 + 20 * 3600)]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "secret-service")]
+
+use std::collections::HashMap;
+use std::io;
+
+use dbus_secret_service::{EncryptionType, SecretService};
+use log::debug;
+
+const APPLICATION: &str = "divera-status";
+
+fn attributes(server: &str) -> HashMap<&str, &str> {
+    HashMap::from([("application", APPLICATION), ("server", server)])
+}
+
+fn connect() -> io::Result<SecretService> {
+    SecretService::connect(EncryptionType::Dh).map_err(io::Error::other)
+}
+
+/// Read the api token for `server` from the freedesktop secret service.
+pub fn load_token(server: &str) -> io::Result<String> {
+    debug!("loading token for {} from secret service", server);
+
+    let service = connect()?;
+    let items = service
+        .search_items(attributes(server))
+        .map_err(io::Error::other)?;
+
+    let item = match (items.unlocked.first(), items.locked.first()) {
+        (Some(item), _) => item,
+        (None, Some(item)) => {
+            item.unlock().map_err(io::Error::other)?;
+            item
+        }
+        (None, None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no token for {} in the secret service, use store-token",
+                    server
+                ),
+            ))
+        }
+    };
+
+    let secret = item.get_secret().map_err(io::Error::other)?;
+    String::from_utf8(secret).map_err(io::Error::other)
+}
+
+/// Save the api token for `server` in the default collection of the secret service.
+pub fn store_token(server: &str, token: &str) -> io::Result<()> {
+    debug!("storing token for {} in secret service", server);
+
+    let service = connect()?;
+    let collection = service.get_default_collection().map_err(io::Error::other)?;
+    collection.ensure_unlocked().map_err(io::Error::other)?;
+
+    collection
+        .create_item(
+            &format!("divera-status token for {}", server),
+            attributes(server),
+            token.as_bytes(),
+            true,
+            "text/plain",
+        )
+        .map_err(io::Error::other)?;
+    Ok(())
+}
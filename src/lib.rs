@@ -1,18 +1,44 @@
 mod api;
 mod api_types;
+mod calendar;
+mod config;
+mod control_socket;
 mod dbus_interface;
+mod divera_status1;
+mod error;
+mod history;
+mod http;
 mod i3blocks;
+mod ics;
+mod keyring;
+pub mod logging;
+mod metrics;
+mod mqtt;
+mod notify;
+mod resolve;
+mod rest_api;
+mod rules;
+mod socket;
+mod tray;
 mod types;
-mod divera_status1;
+mod validate;
+mod webhooks;
 
 use crate::api::{Connection, StatusMap, UserMap};
-use crate::api_types::{Monitor, UserStatus};
-pub use crate::types::Arguments;
-use std::collections::HashMap;
+use crate::api_types::{Event, MutableData, News};
+use crate::calendar::Calendar;
+use crate::config::Account;
+pub use crate::error::Error;
+use crate::rules::Switch;
+pub use crate::types::{Arguments, Command, ListKind};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::str::FromStr;
 
 use std::time::Duration;
 
+use chrono::{DurationRound, TimeZone};
+use log::{debug, warn};
 use reqwest::Client;
 use strfmt::{strfmt, FmtError};
 
@@ -21,19 +47,58 @@ use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::time::timeout;
 
-fn format_output(
+/// Rough time until an event, e.g. "2d 3h" or "45m".
+fn countdown(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Usual colors of the FMS status codes.
+fn fms_color(fms: u8) -> &'static str {
+    match fms {
+        // in service
+        1 | 2 => "3c3",
+        // on the way or on scene
+        3 | 4 | 7 | 8 => "f33",
+        // out of service
+        6 => "888",
+        // speech request and everything else
+        _ => "fc3",
+    }
+}
+
+fn local_time(unix: i64, format: &str) -> String {
+    chrono::Local
+        .timestamp_opt(unix, 0)
+        .single()
+        .map(|time| time.format(format).to_string())
+        .unwrap_or_default()
+}
+
+/// Values for the display format of one account at unix time `now`.
+fn status_data(
     args: &Arguments,
-    monitor: &Monitor,
-    user_status: &UserStatus,
+    resolved: &validate::Resolved,
+    mutable_data: &MutableData,
     user_map: &UserMap,
     status_map: &StatusMap,
-) -> String {
+    now: i64,
+) -> HashMap<String, String> {
+    let monitor = mutable_data.monitor();
     let mut data: HashMap<String, String> = HashMap::new();
 
     // user status
     {
         let user_status = status_map
-            .get(&user_status.status_id().to_string())
+            .get(&mutable_data.status().status_id().to_string())
             .expect("user status not in cached data");
         data.insert("status_name".to_string(), user_status.name().clone());
         data.insert("status_color".to_string(), user_status.color_hex().clone());
@@ -48,6 +113,12 @@ fn format_output(
             .clone();
 
         let user_count = monitor.basic().get(status_id).unwrap().count().to_string();
+        if args.privacy {
+            data.insert(status_id.to_string(), user_count.clone());
+            data.insert(format!("{}_count", status_id), user_count);
+            data.insert(format!("{}_color", status_id), status_color);
+            continue;
+        }
 
         let user_names: Vec<String> = status
             .users()
@@ -66,12 +137,94 @@ fn format_output(
         data.insert(format!("{}_color", status_id), status_color);
     }
 
+    // statuses by name, without shadowing the other values
+    for (status_alias, status_id) in resolve::unique_aliases(status_map) {
+        for suffix in ["", "_count", "_color"] {
+            if let Some(value) = data.get(&format!("{}{}", status_id, suffix)).cloned() {
+                data.entry(format!("{}{}", status_alias, suffix))
+                    .or_insert(value);
+            }
+        }
+    }
+
+    // news
+    {
+        let news = mutable_data.news();
+        data.insert("news_unread".to_string(), news.unread().count().to_string());
+        data.insert(
+            "news_latest_title".to_string(),
+            news.latest()
+                .map(|news| news.title().clone())
+                .unwrap_or_default(),
+        );
+    }
+
+    // events
+    {
+        let next_event = match mutable_data.events().upcoming(now).first() {
+            Some(event) if *event.start() <= now => format!("{} (now)", event.title()),
+            Some(event) => format!("{} in {}", event.title(), countdown(event.start() - now)),
+            None => String::new(),
+        };
+        data.insert("next_event".to_string(), next_event);
+    }
+
+    // status plan
+    {
+        let next_planned_status = mutable_data
+            .user()
+            .as_ref()
+            .and_then(|user| mutable_data.statusplan().next_change(*user.id(), now))
+            .map(|planned| {
+                let name = status_map
+                    .get(&planned.status_id().to_string())
+                    .map(|status| status.name().clone())
+                    .unwrap_or_else(|| planned.status_id().to_string());
+                format!("{} at {}", name, local_time(*planned.start(), "%a %H:%M"))
+            })
+            .unwrap_or_default();
+        data.insert("next_planned_status".to_string(), next_planned_status);
+
+        for hours in 0..=validate::PLAN_HOURS {
+            data.insert(
+                format!("planned_available_{}h", hours),
+                mutable_data
+                    .planned_users(&resolved.available_statuses, now + hours * 3600)
+                    .len()
+                    .to_string(),
+            );
+        }
+    }
+
+    // vehicles
+    {
+        let mut vehicles: Vec<String> = Vec::new();
+        for vehicle in mutable_data.vehicles() {
+            let color = fms_color(*vehicle.fms());
+            data.insert(
+                format!("vehicle_{}_fms", vehicle.id()),
+                vehicle.fms().to_string(),
+            );
+            data.insert(format!("vehicle_{}_color", vehicle.id()), color.to_string());
+
+            let text = format!("{} {}", vehicle.shortname(), vehicle.fms());
+            vehicles.push(if args.no_pango {
+                text
+            } else if args.escape_quotes {
+                format!("<span color=\\\"#{}\\\">{}</span>", color, text)
+            } else {
+                format!("<span color=\"#{}\">{}</span>", color, text)
+            });
+        }
+        data.insert("vehicles".to_string(), vehicles.join(" "));
+    }
+
     // full and short text
     {
         let mut full_statuses: Vec<String> = Vec::new();
         let mut short_statuses: Vec<String> = Vec::new();
 
-        for status in args.shown_statuses.split(',') {
+        for status in &resolved.shown_statuses {
             // check for status with no users
             if data
                 .get(&format!("{}_count", status))
@@ -87,7 +240,7 @@ fn format_output(
                         &format!("<span color=\\\"#{{{0}_color}}\\\">{{{0}}}</span>", status),
                         &data,
                     )
-                        .unwrap(),
+                    .unwrap(),
                 );
                 short_statuses.push(
                     strfmt(
@@ -97,17 +250,15 @@ fn format_output(
                         ),
                         &data,
                     )
-                        .unwrap(),
+                    .unwrap(),
                 );
-
-            }
-            else {
+            } else {
                 full_statuses.push(
                     strfmt(
                         &format!("<span color=\"#{{{0}_color}}\">{{{0}}}</span>", status),
                         &data,
                     )
-                        .unwrap(),
+                    .unwrap(),
                 );
                 short_statuses.push(
                     strfmt(
@@ -117,9 +268,8 @@ fn format_output(
                         ),
                         &data,
                     )
-                        .unwrap(),
+                    .unwrap(),
                 );
-
             }
         }
 
@@ -127,7 +277,11 @@ fn format_output(
         data.insert("short_text".to_string(), short_statuses.join("-"));
     }
 
-    match strfmt(&args.display_format, &data) {
+    data
+}
+
+fn format_output(args: &Arguments, data: &HashMap<String, String>) -> String {
+    match strfmt(&args.display_format, data) {
         Ok(output) => output,
         Err(err) => match err {
             FmtError::Invalid(err) => {
@@ -147,108 +301,1080 @@ async fn wait_update(rx: &mut Receiver<Update>) -> Update {
     rx.recv().await.expect("channel closed by reading thread")
 }
 
-pub async fn start(args: Arguments, token: String) -> Result<(), reqwest::Error> {
-    // parse args
-    let status_order: Vec<u32> = args
-        .status_order
-        .split(',')
-        .map(|status| u32::from_str(status).expect("invalid status id in order"))
+fn connect(args: &Arguments, server: &str, token: String) -> Result<Connection, reqwest::Error> {
+    Ok(Connection::new(
+        Client::builder()
+            .https_only(true)
+            .timeout(api::REQUEST_TIMEOUT)
+            .build()?,
+        server.to_string(),
+        token,
+        args.redact_names,
+    ))
+}
+
+/// Pull the mutable data and the vehicles if they are shown.
+async fn pull_data(
+    connection: &Connection,
+    resolved: &validate::Resolved,
+) -> Result<MutableData, reqwest::Error> {
+    let mut data = connection.pull_mutable().await?;
+    if resolved.vehicles {
+        data.vehicles = connection.pull_vehicles().await?;
+    }
+    Ok(data)
+}
+
+/// Connection and cached data of one account.
+struct AccountState {
+    account: Account,
+    connection: Connection,
+    user_map: UserMap,
+    status_map: StatusMap,
+    resolved: validate::Resolved,
+    data: MutableData,
+    /// unix time of the last status change by hand, through the daemon or noticed in the pulled data
+    manual_change: Option<i64>,
+    /// status last set through the daemon
+    last_set_status: Option<u32>,
+    /// what set the current status, for {status_source}
+    status_source: String,
+}
+
+impl AccountState {
+    /// Pull the mutable data and return unread news that were not there before.
+    async fn pull_mutable(&mut self) -> Result<Vec<News>, reqwest::Error> {
+        let known_news: HashSet<u32> = self
+            .data
+            .news()
+            .items()
+            .values()
+            .map(|news| *news.id())
+            .collect();
+
+        let old_status = *self.data.status().status_id();
+        self.data = pull_data(&self.connection, &self.resolved).await?;
+
+        let new_status = *self.data.status().status_id();
+        if new_status != old_status && Some(new_status) != self.last_set_status {
+            debug!("status{} changed by hand", self.account.describe());
+            self.manual_change = Some(api::unix_now());
+            self.status_source = "manual".to_string();
+        }
+
+        Ok(self
+            .data
+            .news()
+            .unread()
+            .filter(|news| !known_news.contains(news.id()))
+            .cloned()
+            .collect())
+    }
+
+    async fn mark_news_read(&self) -> Result<(), reqwest::Error> {
+        for news in self.data.news().unread() {
+            self.connection.mark_news_read(*news.id()).await?;
+        }
+        Ok(())
+    }
+
+    /// Set a status by id or name for a rule.
+    async fn set_automatic_status(
+        &mut self,
+        status: &str,
+        source: &str,
+    ) -> Result<(), reqwest::Error> {
+        match resolve::resolve_status(&self.status_map, status) {
+            Ok(id) => {
+                self.connection.set_status_id(id).await?;
+                self.last_set_status = Some(id);
+                self.status_source = source.to_string();
+            }
+            Err(err) => warn!("{}{}", err, self.account.describe()),
+        }
+        Ok(())
+    }
+
+    /// Set a status by id or name chosen by hand, rules do not replace it for --schedule-grace.
+    async fn set_manual_status(
+        &mut self,
+        status: &str,
+        source: &str,
+    ) -> Result<(), reqwest::Error> {
+        match resolve::resolve_status(&self.status_map, status) {
+            Ok(id) => self.set_manual_status_id(id, source).await?,
+            Err(err) => warn!("{}{}", err, self.account.describe()),
+        }
+        Ok(())
+    }
+
+    async fn set_manual_status_id(&mut self, id: u32, source: &str) -> Result<(), reqwest::Error> {
+        self.connection.set_status_id(id).await?;
+        self.last_set_status = Some(id);
+        self.manual_change = Some(api::unix_now());
+        self.status_source = source.to_string();
+        Ok(())
+    }
+
+    /// Set the next or previous status from the status order.
+    async fn cycle_status(&mut self, forward: bool) -> Result<(), reqwest::Error> {
+        let status_order = &self.resolved.status_order;
+        if status_order.is_empty() {
+            warn!("no status order{}", self.account.describe());
+            return Ok(());
+        }
+
+        let current_status = status_order
+            .iter()
+            .enumerate()
+            .find(|item| item.1 == self.data.status().status_id());
+
+        let index = current_status.map(|status| status.0).unwrap_or(0) as i32;
+        let new_index =
+            if forward { index + 1 } else { index - 1 }.rem_euclid(status_order.len() as i32);
+
+        self.set_manual_status_id(status_order[new_index as usize], "manual")
+            .await
+    }
+}
+
+/// Connect to all accounts, pull their data and validate the configuration.
+async fn setup_accounts(args: &Arguments) -> Result<Vec<AccountState>, Error> {
+    let mut pulled = Vec::new();
+    for account in args.accounts()? {
+        let connection = connect(args, &account.server, account.get_token()?)?;
+
+        let (user_map, status_map) = connection.pull_static().await?;
+        pulled.push((account, connection, user_map, status_map));
+    }
+
+    let resolved = validate::validate(
+        args.display_format(),
+        &pulled
+            .iter()
+            .map(|(account, _, _, status_map)| (account, status_map))
+            .collect::<Vec<_>>(),
+    )?;
+
+    let mut states = Vec::new();
+    for ((account, connection, user_map, status_map), resolved) in pulled.into_iter().zip(resolved)
+    {
+        debug!(
+            "using order{}: {:?}",
+            account.describe(),
+            resolved.status_order
+        );
+        let data = pull_data(&connection, &resolved).await?;
+        states.push(AccountState {
+            account,
+            connection,
+            user_map,
+            status_map,
+            resolved,
+            data,
+            manual_change: None,
+            last_set_status: None,
+            status_source: String::new(),
+        });
+    }
+    Ok(states)
+}
+
+/// Format the output for all accounts.
+///
+/// The values of each account are prefixed with its name, full_text and short_text combine all accounts.
+fn render(args: &Arguments, states: &[AccountState]) -> String {
+    let now = api::unix_now();
+    let mut data: HashMap<String, String> = HashMap::new();
+    let mut full_texts: Vec<String> = Vec::new();
+    let mut short_texts: Vec<String> = Vec::new();
+
+    for state in states {
+        let mut account_data = status_data(
+            args,
+            &state.resolved,
+            &state.data,
+            &state.user_map,
+            &state.status_map,
+            now,
+        );
+        account_data.insert("status_source".to_string(), state.status_source.clone());
+        for (key, value) in account_data {
+            if key == "full_text" && !value.is_empty() {
+                full_texts.push(value.clone());
+            } else if key == "short_text" && !value.is_empty() {
+                short_texts.push(value.clone());
+            }
+            data.insert(state.account.placeholder(&key), value);
+        }
+    }
+    data.insert("full_text".to_string(), full_texts.join(" | "));
+    data.insert("short_text".to_string(), short_texts.join(" | "));
+
+    format_output(args, &data)
+}
+
+/// Print the formatted output once.
+pub async fn get(args: &Arguments) -> Result<(), Error> {
+    let states = setup_accounts(args).await?;
+
+    println!("{}", render(args, &states));
+    Ok(())
+}
+
+/// Set the status of the user once.
+pub async fn set(args: &Arguments, status: &str) -> Result<(), Error> {
+    let account = args.selected_account()?;
+    let connection = connect(args, &account.server, account.get_token()?)?;
+
+    let (_, status_map) = connection.pull_static().await?;
+    let status_id = resolve::resolve_status(&status_map, status)?;
+
+    connection.set_status_id(status_id).await?;
+    Ok(())
+}
+
+/// Print the statuses or users of the unit as tab separated lines.
+pub async fn list(args: &Arguments, what: ListKind) -> Result<(), Error> {
+    let account = args.selected_account()?;
+    let connection = connect(args, &account.server, account.get_token()?)?;
+
+    let (user_map, status_map) = connection.pull_static().await?;
+
+    let mut lines: Vec<(u32, String)> = match what {
+        ListKind::Statuses => status_map
+            .iter()
+            .map(|(id, status)| {
+                (
+                    u32::from_str(id).unwrap_or_default(),
+                    format!("{}\t{}\t#{}", id, status.name(), status.color_hex()),
+                )
+            })
+            .collect(),
+        ListKind::Users => user_map
+            .iter()
+            .map(|(id, user)| {
+                (
+                    u32::from_str(id).unwrap_or_default(),
+                    format!("{}\t{}", id, user.name()),
+                )
+            })
+            .collect(),
+    };
+    lines.sort();
+
+    for (_, line) in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Print the number and names of available members for each hour of the coming day.
+pub async fn plan(args: &Arguments) -> Result<(), Error> {
+    let states = setup_accounts(args).await?;
+    let state = match args.account() {
+        Some(name) => states
+            .iter()
+            .find(|state| &state.account.name == name)
+            .ok_or_else(|| Error::InvalidConfig(vec![format!("unknown account {}", name)]))?,
+        None => states.first().expect("no accounts"),
+    };
+
+    let next_hour = chrono::Local::now()
+        .duration_trunc(chrono::Duration::hours(1))
+        .expect("invalid local time")
+        .timestamp()
+        + 3600;
+
+    for hours in 0..validate::PLAN_HOURS {
+        let at = next_hour + hours * 3600;
+        let users = state
+            .data
+            .planned_users(&state.resolved.available_statuses, at);
+        let names: Vec<&str> = users
+            .iter()
+            .filter_map(|id| state.user_map.get(&id.to_string()))
+            .map(|user| user.name().as_str())
+            .collect();
+        println!(
+            "{}\t{}\t{}",
+            local_time(at, "%a %H:%M"),
+            users.len(),
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Print the reports of the recorded history for --account or the first account.
+#[cfg(feature = "history")]
+pub fn history(args: &Arguments) -> Result<(), Error> {
+    let Some(path) = args.history() else {
+        return Err(Error::InvalidConfig(vec![
+            "the history needs --history".to_string()
+        ]));
+    };
+    let account = match args.account() {
+        Some(name) => name.clone(),
+        None => args
+            .accounts()?
+            .first()
+            .map(|account| account.name.clone())
+            .unwrap_or_default(),
+    };
+
+    // longer gaps between the polls mean that the daemon was not running
+    let max_gap = 3 * args.interval.max(args.error_interval);
+
+    let history = history::History::open(path)?;
+    history::report(&history, &account, max_gap.as_secs() as i64)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "history"))]
+pub fn history(_args: &Arguments) -> Result<(), Error> {
+    Err(Error::Io(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the history needs the feature `history`",
+    )))
+}
+
+/// Print the upcoming events of all accounts as tab separated lines or as iCalendar.
+pub async fn events(args: &Arguments, ics: bool) -> Result<(), Error> {
+    let mut pulled: Vec<(Account, MutableData)> = Vec::new();
+    for account in args.accounts()? {
+        let connection = connect(args, &account.server, account.get_token()?)?;
+        let data = connection.pull_mutable().await?;
+        pulled.push((account, data));
+    }
+
+    let now = api::unix_now();
+    let mut events: Vec<(&Account, &Event)> = pulled
+        .iter()
+        .flat_map(|(account, data)| {
+            data.events()
+                .upcoming(now)
+                .into_iter()
+                .map(move |event| (account, event))
+        })
         .collect();
-    if args.debug {
-        println!("debug: using order: {:?}", status_order);
+    events.sort_by_key(|(_, event)| *event.start());
+
+    if ics {
+        print!("{}", ics::export(&events, now));
+        return Ok(());
     }
 
-    // set up connection
-    let connection = Connection::new(
-        Client::builder().https_only(true).build()?,
-        args.server().clone(),
-        token,
-        args.debug,
-    );
+    for (account, event) in events {
+        let start = local_time(*event.start(), "%Y-%m-%d %H:%M");
+        let prefix = if account.name.is_empty() {
+            String::new()
+        } else {
+            format!("{}\t", account.name)
+        };
+        println!("{}{}\t{}\t{}", prefix, event.id(), start, event.title());
+    }
+    Ok(())
+}
+
+/// Check that the servers are reachable, the tokens are accepted and the configuration is valid.
+pub async fn check(args: &Arguments) -> Result<(), Error> {
+    let accounts = args.accounts()?;
+    let mut status_maps = Vec::new();
+
+    for account in &accounts {
+        let connection = connect(args, &account.server, account.get_token()?)?;
+
+        connection.ping().await?;
+        println!("server {} is reachable", account.server);
+
+        let (_, status_map) = match connection.pull_static().await {
+            Ok(data) => data,
+            Err(err) if err.status().is_some_and(|status| status.is_client_error()) => {
+                return Err(Error::InvalidConfig(vec![format!(
+                    "token{} was rejected by the server: {}",
+                    account.describe(),
+                    err
+                )]));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        println!("token{} is valid", account.describe());
+        status_maps.push(status_map);
+    }
+
+    let account_maps = accounts.iter().zip(status_maps.iter()).collect::<Vec<_>>();
+    validate::validate(args.display_format(), &account_maps)?;
+    validate::validate_rules(
+        "session_rule",
+        args.session_rules()?
+            .iter()
+            .map(|rule| (rule.account.as_deref(), rule.status.as_str())),
+        &account_maps,
+    )?;
+    validate::validate_rules(
+        "schedule",
+        args.schedule()?
+            .iter()
+            .map(|rule| (rule.account.as_deref(), rule.status.as_str())),
+        &account_maps,
+    )?;
+    let calendars = args.calendars()?;
+    validate::validate_rules(
+        "calendar",
+        calendars.iter().flat_map(|calendar| {
+            calendar.mappings.iter().flat_map(|mapping| {
+                [Some(&mapping.status), mapping.end_status.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|status| (calendar.account.as_deref(), status.as_str()))
+            })
+        }),
+        &account_maps,
+    )?;
+    println!("configuration is valid");
+    Ok(())
+}
+
+/// Save the token in the secret service.
+///
+/// Uses the configured token source or reads a line from stdin, e.g. `pass show divera | divera-status store-token`.
+pub fn store_token(args: &Arguments) -> Result<(), Error> {
+    let account = args.selected_account()?;
+    let token = if account.has_token_source() {
+        account.get_token()?
+    } else {
+        eprint!("token: ");
+        let mut buffer = String::new();
+        std::io::stdin().read_line(&mut buffer)?;
+        buffer.trim().to_string()
+    };
+
+    if token.is_empty() {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "empty token",
+        )));
+    }
+
+    save_to_keyring(&account.server, &token)?;
+    eprintln!("token stored in the secret service");
+    Ok(())
+}
+
+#[cfg(feature = "secret-service")]
+fn save_to_keyring(server: &str, token: &str) -> Result<(), std::io::Error> {
+    keyring::store_token(server, token)
+}
 
+#[cfg(not(feature = "secret-service"))]
+fn save_to_keyring(_server: &str, _token: &str) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "saving the token needs the feature `secret-service`",
+    ))
+}
+
+#[cfg(feature = "rest-api")]
+fn read_first_line(path: &std::path::Path) -> Result<String, std::io::Error> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
+
+fn save_to_file(path: &std::path::Path, token: &str) -> Result<(), std::io::Error> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", token)
+}
+
+/// Log in with username and password and save the access key to the token file or the keyring.
+pub async fn login(args: &Arguments, username: Option<&str>) -> Result<(), Error> {
+    let account = args.selected_account()?;
+    if account.token_file.is_none() && !account.keyring {
+        return Err(Error::InvalidConfig(vec![format!(
+            "login needs a token file or the keyring{} to know where to save the token",
+            account.describe()
+        )]));
+    }
+
+    let username = match username {
+        Some(username) => username.to_string(),
+        None => {
+            eprint!("email: ");
+            let mut buffer = String::new();
+            std::io::stdin().read_line(&mut buffer)?;
+            buffer.trim().to_string()
+        }
+    };
+    let password = rpassword::prompt_password("password: ")?;
+
+    let connection = connect(args, &account.server, String::new())?;
+    let token = connection.login(&username, &password).await?;
+
+    if let Some(token_file) = &account.token_file {
+        save_to_file(token_file, &token)?;
+        eprintln!("token saved to {}", token_file.display());
+    } else {
+        save_to_keyring(&account.server, &token)?;
+        eprintln!("token stored in the secret service");
+    }
+    Ok(())
+}
+
+/// The state of the named account or the first one.
+fn find_state<'a>(
+    states: &'a mut [AccountState],
+    account: Option<&str>,
+) -> Option<&'a mut AccountState> {
+    match account {
+        Some(name) => states.iter_mut().find(|state| state.account.name == name),
+        None => states.first_mut(),
+    }
+}
+
+/// Status changes of the schedule and the calendars after `from` until `until`, the earliest first.
+fn switches(
+    schedule: &[rules::ScheduleRule],
+    calendars: &[Calendar],
+    from: i64,
+    until: i64,
+) -> Vec<Switch> {
+    let mut switches = rules::schedule_switches(schedule, from, until);
+    for calendar in calendars {
+        switches.extend(calendar.switches(from, until));
+    }
+    switches.sort_by_key(|switch| switch.time);
+    switches
+}
+
+/// Set the statuses of the switches.
+///
+/// Skips switches if the status was changed by hand less than --schedule-grace before.
+/// Returns false if a status could not be set.
+async fn apply_switches(
+    args: &Arguments,
+    switches: Vec<Switch>,
+    states: &mut [AccountState],
+) -> bool {
+    let mut applied = true;
+    for switch in switches {
+        let Some(state) = find_state(states, switch.account.as_deref()) else {
+            continue;
+        };
+
+        if state
+            .manual_change
+            .is_some_and(|changed| changed > switch.time - args.schedule_grace.as_secs() as i64)
+        {
+            debug!(
+                "skipping {:?}, status{} was changed by hand",
+                switch,
+                state.account.describe()
+            );
+            continue;
+        }
+
+        debug!("applying {:?}", switch);
+        if let Err(err) = state
+            .set_automatic_status(&switch.status, &switch.source)
+            .await
+        {
+            warn!("applying {:?} failed: {}", switch, err);
+            applied = false;
+        }
+    }
+    applied
+}
+
+/// Print the status changes of the schedule and the calendars for the next days.
+pub fn schedule(args: &Arguments, days: u32) -> Result<(), Error> {
+    let schedule = args.schedule()?;
+    let calendars: Vec<Calendar> = args.calendars()?.into_iter().map(Calendar::new).collect();
+    let now = api::unix_now();
+
+    for switch in switches(
+        &schedule,
+        &calendars,
+        now,
+        now + i64::from(days) * 24 * 3600,
+    ) {
+        let time = local_time(switch.time, "%a %Y-%m-%d %H:%M");
+        match &switch.account {
+            Some(account) => println!(
+                "{}\t{}\t{}\t{}",
+                time, account, switch.status, switch.source
+            ),
+            None => println!("{}\t{}\t{}", time, switch.status, switch.source),
+        }
+    }
+    Ok(())
+}
+
+/// Longest wait after repeated failures.
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Time until the next update, depending on failed updates and open alarms.
+fn poll_interval(args: &Arguments, states: &[AccountState], failures: u32) -> Duration {
+    if failures > 0 {
+        args.error_interval
+            .saturating_mul(2u32.saturating_pow(failures - 1))
+            .min(MAX_BACKOFF)
+    } else if states
+        .iter()
+        .any(|state| state.data.alarms().open().next().is_some())
+    {
+        args.alarm_interval
+    } else {
+        args.interval
+    }
+}
+
+pub async fn start(mut args: Arguments) -> Result<(), Error> {
     // set up event producers
     #[allow(unused)]
     let (tx, mut rx): (Sender<Update>, Receiver<Update>) = mpsc::channel(64);
 
     #[cfg(feature = "i3blocks")]
-    i3blocks::setup(tx.clone(), args.debug);
-
-    #[cfg(feature = "dbus-interface")]
-    dbus_interface::setup(tx.clone(), args.debug).await;
+    i3blocks::setup(tx.clone(), args.clicks()?, &args.accounts()?)?;
+    #[cfg(not(feature = "i3blocks"))]
+    if !args.clicks()?.is_empty() {
+        warn!("click actions need the feature `i3blocks`");
+    }
 
     // request initial data
-    let (user_map, status_map) = connection.pull_static().await?;
-    let (old_monitor, mut old_user_status) = connection.pull_mutable().await?;
+    let mut states = setup_accounts(&args).await?;
+    let session_rules = args.session_rules()?;
+    let schedule = args.schedule()?;
+    let mut calendars: Vec<Calendar> = args.calendars()?.into_iter().map(Calendar::new).collect();
+    {
+        let account_maps = states
+            .iter()
+            .map(|state| (&state.account, &state.status_map))
+            .collect::<Vec<_>>();
+        validate::validate_rules(
+            "session_rule",
+            session_rules
+                .iter()
+                .map(|rule| (rule.account.as_deref(), rule.status.as_str())),
+            &account_maps,
+        )?;
+        validate::validate_rules(
+            "schedule",
+            schedule
+                .iter()
+                .map(|rule| (rule.account.as_deref(), rule.status.as_str())),
+            &account_maps,
+        )?;
+        validate::validate_rules(
+            "click",
+            args.clicks()?.iter().filter_map(|binding| {
+                Some((binding.account.as_deref(), binding.status.as_deref()?))
+            }),
+            &account_maps,
+        )?;
+        validate::validate_rules(
+            "calendar",
+            calendars.iter().flat_map(|calendar| {
+                calendar.config.mappings.iter().flat_map(|mapping| {
+                    [Some(&mapping.status), mapping.end_status.as_ref()]
+                        .into_iter()
+                        .flatten()
+                        .map(|status| (calendar.config.account.as_deref(), status.as_str()))
+                })
+            }),
+            &account_maps,
+        )?;
+    }
 
-    println!(
-        "{}",
-        format_output(
-            &args,
-            &old_monitor,
-            &old_user_status,
-            &user_map,
-            &status_map
+    #[cfg(feature = "dbus-interface")]
+    dbus_interface::setup(
+        tx.clone(),
+        states
+            .iter()
+            .map(|state| {
+                let mut statuses: Vec<_> = state
+                    .status_map
+                    .iter()
+                    .filter_map(|(id, status)| {
+                        Some((
+                            id.parse().ok()?,
+                            status.name().clone(),
+                            status.color_hex().clone(),
+                        ))
+                    })
+                    .collect();
+                statuses.sort();
+                (state.account.name.clone(), statuses)
+            })
+            .collect(),
+    )
+    .await;
+    #[cfg(feature = "dbus-interface")]
+    dbus_interface::setup_system(tx.clone(), session_rules).await;
+
+    #[cfg(feature = "metrics")]
+    let metrics = match args.metrics {
+        Some(address) => {
+            let metrics = metrics::setup(address).await?;
+            *metrics.lock().unwrap() = metrics::encode(&states);
+            Some(metrics)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "metrics"))]
+    if args.metrics.is_some() {
+        warn!("--metrics needs the feature `metrics`");
+    }
+    #[cfg(feature = "rest-api")]
+    let rest_state = match &args.rest_api {
+        Some(address) => {
+            let token_file = args
+                .rest_api_token_file
+                .as_deref()
+                .expect("token file is required by clap");
+            let token = read_first_line(token_file)?;
+            if token.is_empty() {
+                return Err(Error::InvalidConfig(vec![format!(
+                    "the first line of the rest api token file {} is empty",
+                    token_file.display()
+                )]));
+            }
+            logging::redact(&token);
+            Some(rest_api::setup(address, token, tx.clone()).await?)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "rest-api"))]
+    if args.rest_api.is_some() {
+        warn!("--rest-api needs the feature `rest-api`");
+    }
+    #[cfg(feature = "control-socket")]
+    let control_output = if args.control_socket {
+        Some(control_socket::setup(tx.clone()).await?)
+    } else {
+        None
+    };
+    #[cfg(not(feature = "control-socket"))]
+    if args.control_socket {
+        warn!("--control-socket needs the feature `control-socket`");
+    }
+    #[cfg(feature = "mqtt")]
+    let mut mqtt = args
+        .mqtt()?
+        .map(|config| mqtt::Mqtt::setup(&config, &states, tx.clone()));
+    #[cfg(not(feature = "mqtt"))]
+    if args.mqtt()?.is_some() {
+        warn!("mqtt needs the feature `mqtt`");
+    }
+    #[cfg(feature = "tray")]
+    let tray = args.tray.then(|| {
+        tray::setup(
+            tx.clone(),
+            states.iter().map(tray::TrayAccount::new).collect(),
         )
-    );
+    });
+    #[cfg(not(feature = "tray"))]
+    if args.tray {
+        warn!("--tray needs the feature `tray`");
+    }
+    #[cfg(feature = "webhooks")]
+    let mut webhooks = webhooks::Webhooks::new(args.webhooks()?)?;
+    #[cfg(feature = "webhooks")]
+    webhooks.update(&states);
+    #[cfg(not(feature = "webhooks"))]
+    if !args.webhooks()?.is_empty() {
+        warn!("webhooks need the feature `webhooks`");
+    }
+    #[cfg(feature = "history")]
+    let mut history = match &args.history {
+        Some(path) => Some(history::History::open(path)?),
+        None => None,
+    };
+    #[cfg(not(feature = "history"))]
+    if args.history.is_some() {
+        warn!("--history needs the feature `history`");
+    }
 
-    if args.debug {
-        println!("debug: starting loop");
+    let output = render(&args, &states);
+    println!("{}", output);
+    #[cfg(feature = "rest-api")]
+    if let Some(rest_state) = &rest_state {
+        *rest_state.lock().unwrap() = rest_api::state(&states, &output);
+    }
+    #[cfg(feature = "control-socket")]
+    if let Some(control_output) = &control_output {
+        control_output.lock().unwrap().clone_from(&output);
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &mut mqtt {
+        mqtt.publish(&states);
     }
+
+    debug!("starting loop");
+    let mut failures: u32 = 0;
+    let mut switches_checked = api::unix_now();
     loop {
-        if let Ok(update) = timeout(
-            Duration::from_secs(args.interval as u64),
-            wait_update(&mut rx),
+        let mut interval = poll_interval(&args, &states, failures);
+        if let Some(switch) = switches(
+            &schedule,
+            &calendars,
+            switches_checked,
+            switches_checked + 8 * 24 * 3600,
         )
-        .await
+        .first()
         {
-            if args.debug {
-                println!("debug: Got event: {:?}", update);
-            }
-            if update == Update::StatusPrev || update == Update::StatusNext {
-                let current_status = status_order
-                    .iter()
-                    .enumerate()
-                    .find(|item| item.1 == old_user_status.status_id());
-
-                let index = current_status.map(|status| status.0).unwrap_or(0) as i32;
-                let new_index = if update == Update::StatusPrev {
-                    index - 1
-                } else {
-                    index + 1
-                }
-                .rem_euclid(status_order.len() as i32);
+            let until_switch = (switch.time - api::unix_now()).max(0) as u64;
+            interval = interval.min(Duration::from_secs(until_switch + 1));
+        }
+        debug!("waiting for {:?}", interval);
 
-                connection
-                    .set_status_id(status_order[new_index as usize])
-                    .await?;
+        let mut failed = false;
+        if let Ok(update) = timeout(interval, wait_update(&mut rx)).await {
+            debug!("got event: {:?}", update);
+            let result = match &update {
+                Update::StatusNext(account) | Update::StatusPrev(account) => {
+                    match find_state(&mut states, account.as_deref()) {
+                        Some(state) => {
+                            state
+                                .cycle_status(matches!(update, Update::StatusNext(_)))
+                                .await
+                        }
+                        None => {
+                            warn!("got update for unknown account: {:?}", update);
+                            Ok(())
+                        }
+                    }
+                }
+                Update::SetStatus(account, status, source) => {
+                    match find_state(&mut states, account.as_deref()) {
+                        Some(state) => state.set_manual_status(status, source).await,
+                        None => {
+                            warn!("got update for unknown account: {:?}", update);
+                            Ok(())
+                        }
+                    }
+                }
+                Update::RuleStatus(account, status, source) => {
+                    match find_state(&mut states, account.as_deref()) {
+                        Some(state) => state.set_automatic_status(status, source).await,
+                        None => {
+                            warn!("got update for unknown account: {:?}", update);
+                            Ok(())
+                        }
+                    }
+                }
+                Update::EventResponse(id, accept) => {
+                    match states
+                        .iter()
+                        .find(|state| state.data.events().get(*id).is_some())
+                    {
+                        Some(state) => state.connection.respond_event(*id, *accept).await,
+                        None => {
+                            warn!("got response for unknown event {}", id);
+                            Ok(())
+                        }
+                    }
+                }
+                Update::AlarmResponse(id, response_id) => {
+                    match states
+                        .iter()
+                        .find(|state| state.data.alarms().get(*id).is_some())
+                    {
+                        Some(state) => state.connection.respond_alarm(*id, *response_id).await,
+                        None => {
+                            warn!("got response for unknown alarm {}", id);
+                            Ok(())
+                        }
+                    }
+                }
+                Update::VehicleStatus(id, fms) => {
+                    let state = states
+                        .iter()
+                        .find(|state| {
+                            state
+                                .data
+                                .vehicles()
+                                .iter()
+                                .any(|vehicle| vehicle.id() == id)
+                        })
+                        .or(states.first());
+                    match state {
+                        Some(state) => state.connection.set_vehicle_status(*id, *fms).await,
+                        None => Ok(()),
+                    }
+                }
+                Update::NewsRead => {
+                    let mut result = Ok(());
+                    for state in &states {
+                        if let Err(err) = state.mark_news_read().await {
+                            result = Err(err);
+                        }
+                    }
+                    result
+                }
+                Update::TogglePrivacy => {
+                    args.privacy = !args.privacy;
+                    debug!("privacy mode {}", if args.privacy { "on" } else { "off" });
+                    Ok(())
+                }
+                Update::Reload => Ok(()),
+            };
+            if let Err(err) = result {
+                warn!("{:?} failed: {}", update, err);
+                failed = true;
             }
         }
 
-        if args.debug {
-            println!("debug: updating");
+        for calendar in calendars.iter_mut() {
+            calendar.reload();
+        }
+        let now = api::unix_now();
+        if !apply_switches(
+            &args,
+            switches(&schedule, &calendars, switches_checked, now),
+            &mut states,
+        )
+        .await
+        {
+            failed = true;
         }
+        switches_checked = now;
 
-        let (monitor, user_status) = connection.pull_mutable().await?;
+        debug!("updating");
 
-        println!(
-            "{}",
-            format_output(&args, &monitor, &user_status, &user_map, &status_map)
-        );
+        for state in states.iter_mut() {
+            match state.pull_mutable().await {
+                Ok(new_news) => {
+                    if args.notify {
+                        for news in new_news {
+                            notify::send(news.title(), news.text());
+                        }
+                    }
+                    #[cfg(feature = "history")]
+                    if let Some(history) = &mut history {
+                        if let Err(err) = history.record(state, api::unix_now()) {
+                            warn!(
+                                "recording history{} failed: {}",
+                                state.account.describe(),
+                                err
+                            );
+                        }
+                    }
+                }
+                Err(err) => {
+                    warn!("update{} failed: {}", state.account.describe(), err);
+                    failed = true;
+                }
+            }
+        }
+        failures = if failed {
+            failures.saturating_add(1)
+        } else {
+            0
+        };
+
+        #[cfg(feature = "webhooks")]
+        webhooks.update(&states);
 
-        old_user_status = user_status;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &metrics {
+            *metrics.lock().unwrap() = metrics::encode(&states);
+        }
+
+        let output = render(&args, &states);
+        println!("{}", output);
+        #[cfg(feature = "rest-api")]
+        if let Some(rest_state) = &rest_state {
+            *rest_state.lock().unwrap() = rest_api::state(&states, &output);
+        }
+        #[cfg(feature = "control-socket")]
+        if let Some(control_output) = &control_output {
+            control_output.lock().unwrap().clone_from(&output);
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &mut mqtt {
+            mqtt.publish(&states);
+        }
+        #[cfg(feature = "tray")]
+        if let Some(tray) = &tray {
+            tray::update(tray, &states);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::api::Connection;
     use crate::api::{StatusMap, UserMap};
     use crate::api_types::{
-        BasicMonitorStatus, Monitor, MonitorStatus, MonitorUser, Status, User, UserStatus,
+        Alarm, AlarmList, BasicMonitorStatus, Event, EventList, Monitor, MonitorStatus,
+        MonitorUser, MutableData, News, NewsList, OwnUser, PlannedStatus, Status, StatusPlan, User,
+        UserStatus, Vehicle,
+    };
+    use crate::rules::Switch;
+    use crate::{
+        api, apply_switches, countdown, format_output, poll_interval, render, status_data,
+        validate, AccountState, Arguments, MAX_BACKOFF,
     };
-    use crate::{format_output, Arguments};
+    use clap::Parser;
     use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Server that answers every request with an empty json object, returns its url and the received request lines and bodies.
+    async fn fake_server() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buffer = [0; 4096];
+                let request = loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    data.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length = head
+                        .lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                        .map(|(_, value)| value.trim().parse().unwrap())
+                        .unwrap_or(0);
+                    if body.len() >= length || read == 0 {
+                        break format!("{} {}", head.lines().next().unwrap(), body);
+                    }
+                };
+                received.lock().unwrap().push(request);
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+        (url, requests)
+    }
 
-    fn get_example_data() -> (Monitor, UserStatus, UserMap, StatusMap) {
+    fn get_example_data() -> (MutableData, UserMap, StatusMap) {
         let mut monitor = Monitor {
             basic: HashMap::new(),
             complex: HashMap::new(),
@@ -346,29 +1472,332 @@ mod test {
             },
         );
 
-        (monitor, user_status, user_map, status_map)
+        let mut news = NewsList::default();
+        for (id, title, date, read) in [(1, "old", 100, true), (2, "new", 200, false)] {
+            news.items.insert(
+                id.to_string(),
+                News {
+                    id,
+                    title: title.to_string(),
+                    text: String::new(),
+                    date,
+                    read,
+                },
+            );
+        }
+
+        let mut statusplan = StatusPlan::default();
+        for (id, user_id, status_id, start) in [(5, 7, 4, 3600), (6, 8, 2, 7200)] {
+            statusplan.items.insert(
+                id.to_string(),
+                PlannedStatus {
+                    id,
+                    user_id,
+                    status_id,
+                    start,
+                    end: start + 3600,
+                },
+            );
+        }
+
+        let mut events = EventList::default();
+        for (id, title, start) in [(3, "training", 7200), (4, "meeting", 90000)] {
+            events.items.insert(
+                id.to_string(),
+                Event {
+                    id,
+                    title: title.to_string(),
+                    text: String::new(),
+                    address: String::new(),
+                    start,
+                    end: start + 3600,
+                },
+            );
+        }
+
+        (
+            MutableData {
+                monitor,
+                status: user_status,
+                news,
+                alarms: AlarmList::default(),
+                events,
+                user: Some(OwnUser { id: 7 }),
+                statusplan,
+                vehicles: vec![Vehicle {
+                    id: 10,
+                    shortname: "HLF".to_string(),
+                    fullname: "HLF 20".to_string(),
+                    fms: 2,
+                }],
+            },
+            user_map,
+            status_map,
+        )
+    }
+
+    /// State of an account with the example data, the requests go to `server`.
+    fn account_state(args: &Arguments, server: &str) -> AccountState {
+        let (mutable_data, user_map, status_map) = get_example_data();
+        AccountState {
+            account: args.selected_account().unwrap(),
+            connection: Connection::new(
+                reqwest::Client::builder().no_proxy().build().unwrap(),
+                server.to_string(),
+                String::new(),
+                false,
+            ),
+            user_map,
+            status_map,
+            resolved: validate::Resolved::default(),
+            data: mutable_data,
+            manual_change: None,
+            last_set_status: None,
+            status_source: String::new(),
+        }
     }
 
     #[test]
     fn test_i3blocks_format() {
-        let args = Arguments {
-            token: None,
-            token_file: None,
-            interval: 0,
-            server: "".to_string(),
-            shown_statuses: "1,3,2".to_string(),
-            status_order: "4,1,2,3".to_string(),
-            display_format: "{{\"full_text\": \"{full_text} <span color=\"#{status_color}\">@</span>\", \"short_text\": \"{short_text}\"}}".to_string(),
-            escape_quotes: true,
-            no_pango: false,
-            debug: false,
-        };
-        let (monitor, user_status, user_map, status_map) = get_example_data();
+        let args = Arguments::parse_from([
+            "divera-status",
+            "--token",
+            "",
+            "--shown-statuses",
+            "1,3,2",
+            "--status-order",
+            "4,1,2,3",
+            "--escape-quotes",
+        ]);
+        let (mutable_data, user_map, status_map) = get_example_data();
+        let resolved = validate::validate(
+            args.display_format(),
+            &[(&args.selected_account().unwrap(), &status_map)],
+        )
+        .unwrap()
+        .remove(0);
 
-        let output = format_output(&args, &monitor, &user_status, &user_map, &status_map);
+        let output = format_output(
+            &args,
+            &status_data(&args, &resolved, &mutable_data, &user_map, &status_map, 0),
+        );
 
-        let expected = "{\"full_text\": \"<span color=\"#f1f\">D, A</span> - <span color=\"#f2f\">B</span> <span color=\"#f2f\">@</span>\", \"short_text\": \"<span color=\"#f1f\">2</span>-<span color=\"#f2f\">1</span>\"}".to_string();
+        // --escape-quotes only escapes the spans of {full_text} and {short_text}, not the display format
+        let expected = "{\"full_text\": \"<span color=\\\"#f1f\\\">D, A</span> - <span color=\\\"#f2f\\\">B</span> <span color=\"#f2f\">@</span>\", \"short_text\": \"<span color=\\\"#f1f\\\">2</span>-<span color=\\\"#f2f\\\">1</span>\"}".to_string();
 
         assert_eq!(output, expected);
     }
+
+    #[test]
+    fn test_privacy_format() {
+        let args = Arguments::parse_from([
+            "divera-status",
+            "--token",
+            "",
+            "--shown-statuses",
+            "1,3,2",
+            "--status-order",
+            "4,1,2,3",
+            "--privacy",
+        ]);
+        let (mutable_data, user_map, status_map) = get_example_data();
+        let resolved = validate::validate(
+            args.display_format(),
+            &[(&args.selected_account().unwrap(), &status_map)],
+        )
+        .unwrap()
+        .remove(0);
+
+        // counts instead of names
+        let data = status_data(&args, &resolved, &mutable_data, &user_map, &status_map, 0);
+        assert_eq!(
+            data["full_text"],
+            "<span color=\"#f1f\">2</span> - <span color=\"#f2f\">1</span>"
+        );
+        assert_eq!(data["1"], "2");
+    }
+
+    #[test]
+    fn test_status_names() {
+        let args = Arguments::parse_from([
+            "divera-status",
+            "--token",
+            "",
+            "--shown-statuses",
+            "one,Three,2",
+            "--status-order",
+            "four,one",
+            "--display-format",
+            "{status_name}: {one_count} {one} / {2_count} {two} ({news_unread} {news_latest_title}) {next_event} {planned_available_0h} {planned_available_1h} {planned_available_2h} {vehicles}",
+        ]);
+        let (mutable_data, user_map, status_map) = get_example_data();
+        let resolved = validate::validate(
+            args.display_format(),
+            &[(&args.selected_account().unwrap(), &status_map)],
+        )
+        .unwrap()
+        .remove(0);
+        assert_eq!(resolved.shown_statuses, vec![1, 3, 2]);
+
+        let output = format_output(
+            &args,
+            &status_data(&args, &resolved, &mutable_data, &user_map, &status_map, 0),
+        );
+
+        assert_eq!(
+            output,
+            "two: 2 D, A / 1 B (1 new) training in 2h 0m 3 2 4 <span color=\"#3c3\">HLF 2</span>"
+        );
+    }
+
+    #[test]
+    fn test_poll_interval() {
+        let args = Arguments::parse_from([
+            "divera-status",
+            "--token",
+            "",
+            "--shown-statuses",
+            "1",
+            "--status-order",
+            "1",
+            "--interval",
+            "5m",
+            "--error-interval",
+            "90",
+        ]);
+        let mut states = vec![account_state(&args, "")];
+
+        assert_eq!(poll_interval(&args, &states, 0), Duration::from_secs(300));
+        assert_eq!(poll_interval(&args, &states, 2), Duration::from_secs(180));
+        assert_eq!(poll_interval(&args, &states, 20), MAX_BACKOFF);
+
+        states[0].data.alarms.items.insert(
+            "1".to_string(),
+            Alarm {
+                id: 1,
+                title: "fire".to_string(),
+                text: String::new(),
+                date: 0,
+                closed: false,
+            },
+        );
+        assert_eq!(poll_interval(&args, &states, 0), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_zero_durations() {
+        let args = Arguments::parse_from([
+            "divera-status",
+            "-s",
+            "1",
+            "-o",
+            "1",
+            "--schedule-grace",
+            "0",
+        ]);
+        assert!(args.schedule_grace.is_zero());
+
+        for arg in ["--interval", "--alarm-interval", "--error-interval"] {
+            let result =
+                Arguments::try_parse_from(["divera-status", "-s", "1", "-o", "1", arg, "0"]);
+            assert!(result.is_err_and(|err| err.kind() == clap::error::ErrorKind::ValueValidation));
+        }
+    }
+
+    #[test]
+    fn test_countdown() {
+        assert_eq!(countdown(59), "0m");
+        assert_eq!(countdown(3 * 3600 + 20 * 60), "3h 20m");
+        assert_eq!(countdown(2 * 86400 + 3 * 3600 + 59), "2d 3h");
+    }
+
+    #[test]
+    fn test_accounts_format() {
+        let args = Arguments::parse_from([
+            "divera-status",
+            "--status-order",
+            "",
+            "--shown-statuses",
+            "",
+            "--display-format",
+            "{full_text} / {fw_status_name} {rd_one_count} {rd_short_text} {rd_status_source}",
+        ]);
+        let states: Vec<AccountState> = [("fw", vec![1]), ("rd", vec![2, 3])]
+            .into_iter()
+            .map(|(name, shown_statuses)| {
+                let mut state = account_state(&args, "");
+                state.account.name = name.to_string();
+                state.resolved.shown_statuses = shown_statuses;
+                state.status_source = "schedule".to_string();
+                state
+            })
+            .collect();
+
+        assert_eq!(
+            render(&args, &states),
+            "<span color=\"#f1f\">D, A</span> | <span color=\"#f2f\">B</span> / two 2 <span color=\"#f2f\">1</span> schedule"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_manual_status_kept() {
+        let args = Arguments::parse_from([
+            "divera-status",
+            "--token",
+            "",
+            "--shown-statuses",
+            "1",
+            "--status-order",
+            "1",
+            "--schedule-grace",
+            "30m",
+        ]);
+        let (url, requests) = fake_server().await;
+        let mut states = vec![account_state(&args, &url)];
+
+        states[0]
+            .set_manual_status("three", "remote")
+            .await
+            .unwrap();
+        assert!(states[0].manual_change.is_some());
+
+        let switch = Switch {
+            time: api::unix_now() + 60,
+            account: None,
+            status: "one".to_string(),
+            source: "schedule".to_string(),
+        };
+        assert!(apply_switches(&args, vec![switch.clone()], &mut states).await);
+        assert_eq!(states[0].status_source, "remote");
+        assert_eq!(states[0].last_set_status, Some(3));
+
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("POST /api/v2/statusgeber/set-status"));
+        assert!(requests[0].contains("\"id\":3"));
+
+        // after the grace period the schedule wins
+        states[0].manual_change = Some(api::unix_now() - 3600);
+        assert!(apply_switches(&args, vec![switch], &mut states).await);
+        assert_eq!(states[0].status_source, "schedule");
+        assert_eq!(states[0].last_set_status, Some(1));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_respond_alarm() {
+        let (url, requests) = fake_server().await;
+        let connection = Connection::new(
+            reqwest::Client::builder().no_proxy().build().unwrap(),
+            url,
+            String::new(),
+            false,
+        );
+
+        connection.respond_alarm(7, 2).await.unwrap();
+
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("POST /api/v2/alarms/confirm/7"));
+        assert!(requests[0].ends_with("{\"AlarmConfirm\":{\"response_id\":2}}"));
+    }
 }
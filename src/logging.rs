@@ -0,0 +1,109 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::RwLock;
+
+use env_logger::{Builder, Target};
+use log::LevelFilter;
+
+const REDACTED: &str = "[redacted]";
+
+/// strings that get replaced before anything is written to the log
+static REDACTIONS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Never write `secret` to the log from now on.
+pub fn redact(secret: impl Into<String>) {
+    let secret = secret.into();
+    if secret.is_empty() {
+        return;
+    }
+
+    let mut redactions = REDACTIONS.write().expect("redaction list poisoned");
+    if !redactions.contains(&secret) {
+        redactions.push(secret);
+        // replace longer strings first so a name containing another name is hidden completely
+        redactions.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    }
+}
+
+pub(crate) fn apply_redactions(line: &str) -> String {
+    REDACTIONS
+        .read()
+        .expect("redaction list poisoned")
+        .iter()
+        .fold(line.to_string(), |line, secret| {
+            line.replace(secret.as_str(), REDACTED)
+        })
+}
+
+/// Writer that hides all registered secrets.
+///
+/// env_logger hands over every record with a single write, so secrets are never split between calls.
+struct RedactingWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = apply_redactions(&String::from_utf8_lossy(buf));
+        self.inner.write_all(line.as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Set up the global logger.
+///
+/// `filter` uses the env_logger syntax (`warn,divera_status::api=trace`) and defaults to `RUST_LOG`.
+/// Without a `log_file` everything goes to stderr so stdout stays reserved for the status output.
+pub fn setup(filter: Option<&str>, debug: bool, log_file: Option<&Path>) -> io::Result<()> {
+    let mut builder = Builder::new();
+    builder.filter_level(LevelFilter::Warn);
+
+    if debug {
+        builder.filter_module("divera_status", LevelFilter::Debug);
+    }
+    if let Ok(env_filter) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&env_filter);
+    }
+    if let Some(filter) = filter {
+        builder.parse_filters(filter);
+    }
+
+    let target: Box<dyn Write + Send> = match log_file {
+        Some(path) => {
+            builder.write_style(env_logger::WriteStyle::Never);
+            Box::new(RedactingWriter {
+                inner: OpenOptions::new().create(true).append(true).open(path)?,
+            })
+        }
+        None => Box::new(RedactingWriter {
+            inner: io::stderr(),
+        }),
+    };
+    builder.target(Target::Pipe(target));
+
+    builder.try_init().map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::logging::{apply_redactions, redact};
+
+    #[test]
+    fn test_redaction() {
+        redact("secret-token");
+        redact("Max");
+        redact("Max Mustermann");
+        redact("");
+
+        assert_eq!(
+            apply_redactions("GET /api?accesskey=secret-token by Max Mustermann and Max"),
+            "GET /api?accesskey=[redacted] by [redacted] and [redacted]"
+        );
+    }
+}
@@ -1,13 +1,45 @@
-use divera_status::{start, Arguments};
+use divera_status::{
+    check, events, get, history, list, logging, login, plan, schedule, set, start, store_token,
+    Arguments, Command,
+};
+
+use std::process::ExitCode;
 
 use clap::Parser;
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Arguments::parse();
+async fn run(args: Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    logging::setup(
+        args.log().as_deref(),
+        *args.debug(),
+        args.log_file().as_deref(),
+    )?;
 
-    let token = args.get_token()?;
-    start(args, token).await?;
+    match args.command() {
+        None => start(args).await?,
+        Some(Command::Get) => get(&args).await?,
+        Some(Command::Set { status }) => set(&args, status).await?,
+        Some(Command::List { what }) => list(&args, *what).await?,
+        Some(Command::Schedule { days }) => schedule(&args, *days)?,
+        Some(Command::Plan) => plan(&args).await?,
+        Some(Command::Events { ics }) => events(&args, *ics).await?,
+        Some(Command::History) => history(&args)?,
+        Some(Command::Check) => check(&args).await?,
+        Some(Command::StoreToken) => store_token(&args)?,
+        Some(Command::Login { username }) => login(&args, username.as_deref()).await?,
+    }
 
     Ok(())
 }
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = Arguments::parse();
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
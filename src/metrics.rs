@@ -0,0 +1,224 @@
+#![cfg(feature = "metrics")]
+
+use crate::http;
+use crate::http::Response;
+use crate::AccountState;
+
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Metrics in the prometheus text format, replaced after every update.
+pub type Metrics = Arc<Mutex<String>>;
+
+/// Samples of one metric.
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    samples: Vec<String>,
+}
+
+/// Collects samples grouped by metric, as the text format needs them.
+#[derive(Default)]
+struct Exposition {
+    families: Vec<Family>,
+}
+
+impl Exposition {
+    fn add(
+        &mut self,
+        name: &'static str,
+        kind: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        let index = match self.families.iter().position(|family| family.name == name) {
+            Some(index) => index,
+            None => {
+                self.families.push(Family {
+                    name,
+                    kind,
+                    help,
+                    samples: Vec::new(),
+                });
+                self.families.len() - 1
+            }
+        };
+
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        self.families[index]
+            .samples
+            .push(format!("{}{{{}}} {}", name, labels, value));
+    }
+
+    fn gauge(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        self.add(name, "gauge", help, labels, value)
+    }
+
+    fn counter(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        self.add(name, "counter", help, labels, value)
+    }
+
+    fn encode(&self) -> String {
+        let mut text = String::new();
+        for family in &self.families {
+            let _ = writeln!(text, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(text, "# TYPE {} {}", family.name, family.kind);
+            for sample in &family.samples {
+                let _ = writeln!(text, "{}", sample);
+            }
+        }
+        text
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Metrics of the last update of all accounts.
+pub(crate) fn encode(states: &[AccountState]) -> String {
+    let mut exposition = Exposition::default();
+
+    for state in states {
+        let account = state.account.name.as_str();
+
+        let mut counts = state.data.monitor().basic().iter().collect::<Vec<_>>();
+        counts.sort_by_key(|(id, _)| id.parse::<u32>().unwrap_or(u32::MAX));
+        for (id, status) in counts {
+            let name = state
+                .status_map
+                .get(id)
+                .map(|status| status.name().as_str())
+                .unwrap_or_default();
+            exposition.gauge(
+                "divera_status_members",
+                "Members with the status.",
+                &[("account", account), ("status_id", id), ("status", name)],
+                f64::from(*status.count()),
+            );
+        }
+
+        exposition.gauge(
+            "divera_own_status_id",
+            "Id of the own status.",
+            &[("account", account)],
+            f64::from(*state.data.status().status_id()),
+        );
+        exposition.gauge(
+            "divera_open_alarms",
+            "Alarms that are not closed.",
+            &[("account", account)],
+            state.data.alarms().open().count() as f64,
+        );
+
+        let stats = state.connection.stats();
+        exposition.counter(
+            "divera_api_requests_total",
+            "Requests to the divera api.",
+            &[("account", account)],
+            stats.requests() as f64,
+        );
+        exposition.counter(
+            "divera_api_errors_total",
+            "Failed requests to the divera api.",
+            &[("account", account)],
+            stats.errors() as f64,
+        );
+        exposition.counter(
+            "divera_api_request_duration_seconds_total",
+            "Time spent on requests to the divera api.",
+            &[("account", account)],
+            stats.duration().as_secs_f64(),
+        );
+    }
+
+    exposition.encode()
+}
+
+/// Serve the metrics at http://<address>/metrics.
+pub async fn setup(address: SocketAddr) -> Result<Metrics, std::io::Error> {
+    debug!("serving metrics on {}", address);
+
+    let listener = TcpListener::bind(address).await?;
+    let metrics = Metrics::default();
+    let served = metrics.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let metrics = served.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = respond(stream, &metrics).await {
+                            debug!("error answering metrics request: {}", err);
+                        }
+                    });
+                }
+                Err(err) => warn!("error accepting metrics connection: {}", err),
+            }
+        }
+    });
+    Ok(metrics)
+}
+
+/// Answer a single http request.
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> Result<(), std::io::Error> {
+    let request = http::read_request(&mut stream).await?;
+    let response = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => Response::new(
+            200,
+            "text/plain; version=0.0.4",
+            metrics.lock().unwrap().clone(),
+        ),
+        _ => Response::empty(404),
+    };
+    http::write_response(&mut stream, &response).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::Exposition;
+
+    #[test]
+    fn test_encode() {
+        let mut exposition = Exposition::default();
+        exposition.gauge("members", "Members.", &[("status", "on \"duty\"")], 3.0);
+        exposition.counter("requests_total", "Requests.", &[("account", "fw")], 12.0);
+        exposition.gauge("members", "Members.", &[("status", "off")], 0.5);
+
+        assert_eq!(
+            exposition.encode(),
+            "# HELP members Members.\n\
+             # TYPE members gauge\n\
+             members{status=\"on \\\"duty\\\"\"} 3\n\
+             members{status=\"off\"} 0.5\n\
+             # HELP requests_total Requests.\n\
+             # TYPE requests_total counter\n\
+             requests_total{account=\"fw\"} 12\n"
+        );
+    }
+}
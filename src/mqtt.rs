@@ -0,0 +1,329 @@
+#![cfg(feature = "mqtt")]
+
+use crate::api::StatusMap;
+use crate::config::MqttConfig;
+use crate::types::Update;
+use crate::{logging, AccountState};
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::{debug, warn};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+/// Publishes the state of all accounts and sets the status from the command topics.
+pub struct Mqtt {
+    client: AsyncClient,
+    prefix: String,
+    /// last payload of every state topic, to publish only changes
+    published: HashMap<String, String>,
+}
+
+/// Topic segment of an account, "default" for the one from the arguments.
+fn account_topic(account: &str) -> &str {
+    if account.is_empty() {
+        "default"
+    } else {
+        account
+    }
+}
+
+/// Part of the home assistant ids, lowercase letters, digits and "_" only.
+fn object_id(account: &str) -> String {
+    account_topic(account)
+        .chars()
+        .map(|char| {
+            if char.is_ascii_alphanumeric() {
+                char.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Home assistant discovery messages for the status select, the counts and the alarm of an account.
+fn discovery(config: &MqttConfig, account: &str, status_map: &StatusMap) -> Vec<(String, String)> {
+    let base = format!("{}/{}", config.topic_prefix, account_topic(account));
+    let id = object_id(account);
+    let device = json!({
+        "identifiers": [format!("divera_status_{}", id)],
+        "name": format!("Divera {}", account_topic(account)),
+    });
+    let availability = format!("{}/availability", config.topic_prefix);
+
+    let mut statuses: Vec<(u32, &str)> = status_map
+        .iter()
+        .map(|(status_id, status)| {
+            (
+                status_id.parse().unwrap_or_default(),
+                status.name().as_str(),
+            )
+        })
+        .collect();
+    statuses.sort();
+
+    let mut messages = vec![
+        (
+            format!(
+                "{}/select/divera_{}_status/config",
+                config.discovery_prefix, id
+            ),
+            json!({
+                "name": "Status",
+                "unique_id": format!("divera_{}_status", id),
+                "state_topic": format!("{}/status", base),
+                "command_topic": format!("{}/set", base),
+                "options": statuses.iter().map(|(_, name)| name).collect::<Vec<_>>(),
+                "availability_topic": availability,
+                "device": device,
+            }),
+        ),
+        (
+            format!(
+                "{}/binary_sensor/divera_{}_alarm/config",
+                config.discovery_prefix, id
+            ),
+            json!({
+                "name": "Alarm",
+                "unique_id": format!("divera_{}_alarm", id),
+                "state_topic": format!("{}/alarm", base),
+                "device_class": "safety",
+                "availability_topic": availability,
+                "device": device,
+            }),
+        ),
+    ];
+    for (status_id, name) in statuses {
+        messages.push((
+            format!(
+                "{}/sensor/divera_{}_count_{}/config",
+                config.discovery_prefix, id, status_id
+            ),
+            json!({
+                "name": name,
+                "unique_id": format!("divera_{}_count_{}", id, status_id),
+                "state_topic": format!("{}/count/{}", base, status_id),
+                "unit_of_measurement": "members",
+                "state_class": "measurement",
+                "availability_topic": availability,
+                "device": device,
+            }),
+        ));
+    }
+
+    messages
+        .into_iter()
+        .map(|(topic, config)| (topic, config.to_string()))
+        .collect()
+}
+
+/// Handle the connection, (re)subscribe and announce the entities after every connect.
+async fn run(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    prefix: String,
+    announcements: Vec<(String, String)>,
+    tx: mpsc::Sender<Update>,
+) {
+    let command_topic = format!("{}/+/set", prefix);
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                debug!("connected to mqtt broker");
+                // the requests are only sent while the event loop is polled
+                let (client, command_topic, prefix, announcements) = (
+                    client.clone(),
+                    command_topic.clone(),
+                    prefix.clone(),
+                    announcements.clone(),
+                );
+                tokio::spawn(async move {
+                    let _ = client.subscribe(command_topic, QoS::AtLeastOnce).await;
+                    let _ = client
+                        .publish(
+                            format!("{}/availability", prefix),
+                            QoS::AtLeastOnce,
+                            true,
+                            "online",
+                        )
+                        .await;
+                    for (topic, payload) in announcements {
+                        let _ = client.publish(topic, QoS::AtLeastOnce, true, payload).await;
+                    }
+                });
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let account = publish
+                    .topic
+                    .strip_prefix(&format!("{}/", prefix))
+                    .and_then(|topic| topic.strip_suffix("/set"));
+                let status = String::from_utf8_lossy(&publish.payload).trim().to_string();
+                if let Some(account) = account {
+                    debug!("got status {} for account {} over mqtt", status, account);
+                    let account = (account != "default").then(|| account.to_string());
+                    let _ = tx.send(Update::SetStatus(account, status, "mqtt")).await;
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn!("mqtt connection failed: {}", err);
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+        }
+    }
+}
+
+impl Mqtt {
+    pub(crate) fn setup(
+        config: &MqttConfig,
+        states: &[AccountState],
+        tx: mpsc::Sender<Update>,
+    ) -> Self {
+        debug!("connecting to mqtt broker {}:{}", config.host, config.port);
+
+        let mut options = MqttOptions::new(
+            format!("divera-status-{}", std::process::id()),
+            &config.host,
+            config.port,
+        );
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            format!("{}/availability", config.topic_prefix),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &config.username {
+            let password = config.password.clone().unwrap_or_default();
+            logging::redact(&password);
+            options.set_credentials(username, password);
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, 64);
+        let announcements = states
+            .iter()
+            .flat_map(|state| discovery(config, &state.account.name, &state.status_map))
+            .collect();
+        tokio::spawn(run(
+            eventloop,
+            client.clone(),
+            config.topic_prefix.clone(),
+            announcements,
+            tx,
+        ));
+
+        Mqtt {
+            client,
+            prefix: config.topic_prefix.clone(),
+            published: HashMap::new(),
+        }
+    }
+
+    /// Publish the changed state topics of all accounts.
+    pub(crate) fn publish(&mut self, states: &[AccountState]) {
+        for state in states {
+            let base = format!("{}/{}", self.prefix, account_topic(&state.account.name));
+            let status_id = *state.data.status().status_id();
+            let status = state.status_map.get(&status_id.to_string());
+
+            let mut messages = vec![
+                (
+                    format!("{}/status", base),
+                    status
+                        .map(|status| status.name().clone())
+                        .unwrap_or_default(),
+                ),
+                (format!("{}/status_id", base), status_id.to_string()),
+                (
+                    format!("{}/alarm", base),
+                    if state.data.alarms().open().next().is_some() {
+                        "ON".to_string()
+                    } else {
+                        "OFF".to_string()
+                    },
+                ),
+            ];
+            for (id, count) in state.data.monitor().basic() {
+                messages.push((format!("{}/count/{}", base, id), count.count().to_string()));
+            }
+
+            for (topic, payload) in messages {
+                if self.published.get(&topic) == Some(&payload) {
+                    continue;
+                }
+                // never wait for a broker that is not reachable
+                match self
+                    .client
+                    .try_publish(&topic, QoS::AtLeastOnce, true, payload.as_str())
+                {
+                    Ok(()) => {
+                        self.published.insert(topic, payload);
+                    }
+                    Err(err) => warn!("could not publish {}: {}", topic, err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::discovery;
+    use crate::api::StatusMap;
+    use crate::api_types::Status;
+    use crate::config::MqttConfig;
+
+    #[test]
+    fn test_discovery() {
+        let config = MqttConfig {
+            host: "localhost".to_string(),
+            port: 1883,
+            username: None,
+            password: None,
+            topic_prefix: "divera".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        };
+        let status_map = StatusMap::from([
+            (
+                "2".to_string(),
+                Status {
+                    name: "not available".to_string(),
+                    color_hex: "f00".to_string(),
+                },
+            ),
+            (
+                "1".to_string(),
+                Status {
+                    name: "available".to_string(),
+                    color_hex: "0f0".to_string(),
+                },
+            ),
+        ]);
+
+        let messages = discovery(&config, "Fw Nord", &status_map);
+        let topics: Vec<&str> = messages.iter().map(|(topic, _)| topic.as_str()).collect();
+        assert_eq!(
+            topics,
+            [
+                "homeassistant/select/divera_fw_nord_status/config",
+                "homeassistant/binary_sensor/divera_fw_nord_alarm/config",
+                "homeassistant/sensor/divera_fw_nord_count_1/config",
+                "homeassistant/sensor/divera_fw_nord_count_2/config",
+            ]
+        );
+
+        let select: serde_json::Value = serde_json::from_str(&messages[0].1).unwrap();
+        assert_eq!(select["command_topic"], "divera/Fw Nord/set");
+        assert_eq!(
+            select["options"],
+            serde_json::json!(["available", "not available"])
+        );
+        assert_eq!(
+            discovery(&config, "", &status_map)[0].0,
+            "homeassistant/select/divera_default_status/config"
+        );
+    }
+}
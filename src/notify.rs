@@ -0,0 +1,22 @@
+use std::process::{Command, Stdio};
+use std::thread;
+
+use log::{debug, warn};
+
+/// Show a desktop notification with notify-send without blocking the caller.
+pub fn send(summary: &str, body: &str) {
+    debug!("sending notification: {}", summary);
+
+    let mut command = Command::new("notify-send");
+    command
+        .arg("--app-name=divera-status")
+        .arg(summary)
+        .arg(body)
+        .stdin(Stdio::null());
+
+    thread::spawn(move || match command.status() {
+        Ok(status) if !status.success() => warn!("notify-send failed with {}", status),
+        Ok(_) => {}
+        Err(err) => warn!("could not run notify-send: {}", err),
+    });
+}
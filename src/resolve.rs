@@ -0,0 +1,144 @@
+use crate::api::StatusMap;
+use crate::error::Error;
+
+use std::str::FromStr;
+
+/// Placeholder friendly form of a status name, e.g. "At Work (limited)" -> "at_work_limited".
+pub fn alias(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("_")
+}
+
+fn sorted_ids<'a>(ids: impl Iterator<Item = &'a String>) -> Vec<u32> {
+    let mut ids: Vec<u32> = ids
+        .map(|id| u32::from_str(id).expect("invalid status id from server"))
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Look up a status by its id, name or alias.
+///
+/// Exact names win over aliases so "at work" still works if there is also an "At-Work".
+pub fn resolve_status(status_map: &StatusMap, status: &str) -> Result<u32, Error> {
+    let status = status.trim();
+
+    if let Ok(id) = u32::from_str(status) {
+        return if status_map.contains_key(&id.to_string()) {
+            Ok(id)
+        } else {
+            Err(Error::UnknownStatus(status.to_string()))
+        };
+    }
+
+    let mut ids = sorted_ids(
+        status_map
+            .iter()
+            .filter(|(_, item)| item.name() == status)
+            .map(|(id, _)| id),
+    );
+    if ids.is_empty() {
+        let status_alias = alias(status);
+        ids = sorted_ids(
+            status_map
+                .iter()
+                .filter(|(_, item)| alias(item.name()) == status_alias)
+                .map(|(id, _)| id),
+        );
+    }
+
+    match ids.len() {
+        0 => Err(Error::UnknownStatus(status.to_string())),
+        1 => Ok(ids[0]),
+        _ => Err(Error::AmbiguousStatus(status.to_string(), ids)),
+    }
+}
+
+/// Aliases that belong to exactly one status, usable as placeholder prefix.
+pub fn unique_aliases(status_map: &StatusMap) -> Vec<(String, &String)> {
+    status_map
+        .iter()
+        .map(|(id, status)| (alias(status.name()), id))
+        .filter(|(status_alias, _)| {
+            !status_alias.is_empty()
+                && status_map
+                    .values()
+                    .filter(|status| &alias(status.name()) == status_alias)
+                    .count()
+                    == 1
+        })
+        .collect()
+}
+
+/// Status map with the ids and names, for tests.
+#[cfg(test)]
+pub(crate) fn test_status_map(statuses: &[(&str, &str)]) -> StatusMap {
+    statuses
+        .iter()
+        .map(|(id, name)| {
+            (
+                id.to_string(),
+                crate::api_types::Status {
+                    name: name.to_string(),
+                    color_hex: "fff".to_string(),
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::StatusMap;
+    use crate::error::Error;
+    use crate::resolve::{alias, resolve_status, test_status_map, unique_aliases};
+
+    fn status_map() -> StatusMap {
+        test_status_map(&[
+            ("1", "Available"),
+            ("2", "At Work (limited)"),
+            ("3", "at work"),
+            ("4", "At-Work"),
+            ("5", "Unavailable"),
+        ])
+    }
+
+    #[test]
+    fn test_alias() {
+        assert_eq!(alias("At Work (limited)"), "at_work_limited");
+        assert_eq!(alias("  Verfügbar "), "verfügbar");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let status_map = status_map();
+
+        assert_eq!(resolve_status(&status_map, "5").unwrap(), 5);
+        assert_eq!(resolve_status(&status_map, "available").unwrap(), 1);
+        assert_eq!(resolve_status(&status_map, "at_work_limited").unwrap(), 2);
+        assert_eq!(resolve_status(&status_map, "at work").unwrap(), 3);
+        assert!(matches!(
+            resolve_status(&status_map, "AT WORK"),
+            Err(Error::AmbiguousStatus(_, ids)) if ids == vec![3, 4]
+        ));
+        assert!(matches!(
+            resolve_status(&status_map, "9"),
+            Err(Error::UnknownStatus(_))
+        ));
+    }
+
+    #[test]
+    fn test_unique_aliases() {
+        let status_map = status_map();
+        let mut aliases: Vec<String> = unique_aliases(&status_map)
+            .into_iter()
+            .map(|(status_alias, _)| status_alias)
+            .collect();
+        aliases.sort();
+
+        assert_eq!(aliases, vec!["at_work_limited", "available", "unavailable"]);
+    }
+}
@@ -0,0 +1,394 @@
+#![cfg(feature = "rest-api")]
+
+use crate::http;
+use crate::http::{Request, Response};
+use crate::socket;
+use crate::types::Update;
+use crate::AccountState;
+
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::mpsc;
+
+/// State of the daemon as json, replaced after every update.
+pub type State = Arc<Mutex<String>>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StatusRequest {
+    status: String,
+    account: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct AccountRequest {
+    account: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventRequest {
+    accept: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlarmRequest {
+    response_id: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VehicleRequest {
+    fms: u8,
+}
+
+/// What a request asks for.
+#[derive(Debug, PartialEq)]
+enum Action {
+    State,
+    Update(Update),
+}
+
+fn error(status: u16, message: &str) -> Response {
+    Response::new(
+        status,
+        "application/json",
+        json!({ "error": message }).to_string(),
+    )
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(request: &'a Request) -> Result<T, Response> {
+    serde_json::from_slice(&request.body)
+        .map_err(|err| error(400, &format!("invalid body: {}", err)))
+}
+
+/// Like parse_body, but an empty body is the default.
+fn parse_optional_body<'a, T: Deserialize<'a> + Default>(
+    request: &'a Request,
+) -> Result<T, Response> {
+    if request.body.iter().all(u8::is_ascii_whitespace) {
+        Ok(T::default())
+    } else {
+        parse_body(request)
+    }
+}
+
+/// Compare without returning early, so the token can not be guessed by timing.
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn route(request: &Request, token: &str) -> Result<Action, Response> {
+    let authorized = request
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| token_matches(given, token));
+    if !authorized {
+        return Err(error(401, "missing or wrong bearer token"));
+    }
+
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let update = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["state"]) => return Ok(Action::State),
+        ("POST", ["status"]) => {
+            let body: StatusRequest = parse_body(request)?;
+            Update::SetStatus(body.account, body.status, "remote")
+        }
+        ("POST", ["status", "next"]) => {
+            Update::StatusNext(parse_optional_body::<AccountRequest>(request)?.account)
+        }
+        ("POST", ["status", "previous"]) => {
+            Update::StatusPrev(parse_optional_body::<AccountRequest>(request)?.account)
+        }
+        ("POST", ["reload"]) => Update::Reload,
+        ("POST", ["news", "read"]) => Update::NewsRead,
+        ("POST", ["events", id, "response"]) => {
+            let id = id.parse().map_err(|_| error(404, "invalid event id"))?;
+            Update::EventResponse(id, parse_body::<EventRequest>(request)?.accept)
+        }
+        ("POST", ["alarms", id, "response"]) => {
+            let id = id.parse().map_err(|_| error(404, "invalid alarm id"))?;
+            Update::AlarmResponse(id, parse_body::<AlarmRequest>(request)?.response_id)
+        }
+        ("POST", ["vehicles", id, "status"]) => {
+            let id = id.parse().map_err(|_| error(404, "invalid vehicle id"))?;
+            let fms = parse_body::<VehicleRequest>(request)?.fms;
+            if fms > 9 {
+                return Err(error(400, "fms status must be between 0 and 9"));
+            }
+            Update::VehicleStatus(id, fms)
+        }
+        (_, ["state" | "status" | "reload"])
+        | (_, ["status", "next" | "previous"])
+        | (_, ["news", "read"])
+        | (_, ["events" | "alarms", _, "response"])
+        | (_, ["vehicles", _, "status"]) => return Err(error(405, "method not allowed")),
+        _ => return Err(error(404, "not found")),
+    };
+    Ok(Action::Update(update))
+}
+
+/// Json state of all accounts and the last output line.
+pub(crate) fn state(states: &[AccountState], output: &str) -> String {
+    let accounts: Vec<_> = states
+        .iter()
+        .map(|state| {
+            let status_id = *state.data.status().status_id();
+            let status = state.status_map.get(&status_id.to_string());
+            let mut statuses: Vec<_> = state
+                .data
+                .monitor()
+                .basic()
+                .iter()
+                .map(|(id, count)| {
+                    (
+                        id.parse::<u32>().unwrap_or_default(),
+                        state.status_map.get(id).map(|status| status.name()),
+                        *count.count(),
+                    )
+                })
+                .collect();
+            statuses.sort();
+            json!({
+                "name": state.account.name,
+                "status": {
+                    "id": status_id,
+                    "name": status.map(|status| status.name()),
+                    "color": status.map(|status| status.color_hex()),
+                },
+                "status_source": state.status_source,
+                "statuses": statuses
+                    .into_iter()
+                    .map(|(id, name, members)| json!({ "id": id, "name": name, "members": members }))
+                    .collect::<Vec<_>>(),
+                "open_alarms": state.data.alarms().open().count(),
+                "news_unread": state.data.news().unread().count(),
+            })
+        })
+        .collect();
+    json!({ "output": output, "accounts": accounts }).to_string()
+}
+
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    token: &str,
+    tx: &mpsc::Sender<Update>,
+    state: &State,
+) -> Result<(), std::io::Error> {
+    let request = http::read_request(&mut stream).await?;
+    debug!("rest api request: {} {}", request.method, request.path);
+
+    let response = match route(&request, token) {
+        Ok(Action::State) => Response::new(200, "application/json", state.lock().unwrap().clone()),
+        Ok(Action::Update(update)) => match tx.send(update).await {
+            Ok(()) => Response::empty(204),
+            Err(_) => error(500, "daemon is shutting down"),
+        },
+        Err(response) => response,
+    };
+    http::write_response(&mut stream, &response).await
+}
+
+fn spawn_handler<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: S,
+    token: &Arc<String>,
+    tx: &mpsc::Sender<Update>,
+    state: &State,
+) {
+    let (token, tx, state) = (token.clone(), tx.clone(), state.clone());
+    tokio::spawn(async move {
+        if let Err(err) = handle(stream, &token, &tx, &state).await {
+            debug!("error answering rest api request: {}", err);
+        }
+    });
+}
+
+/// Serve the api on a local address, or on a unix socket if `address` is no ip address and port.
+pub async fn setup(
+    address: &str,
+    token: String,
+    tx: mpsc::Sender<Update>,
+) -> Result<State, std::io::Error> {
+    let state = State::default();
+    let token = Arc::new(token);
+
+    if let Ok(address) = address.parse::<SocketAddr>() {
+        // the token and the requests are sent in plain text
+        if !address.ip().is_loopback() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("rest api address {} is not a loopback address", address),
+            ));
+        }
+        debug!("serving rest api on {}", address);
+        let listener = TcpListener::bind(address).await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => spawn_handler(stream, &token, &tx, &state),
+                    Err(err) => warn!("error accepting rest api connection: {}", err),
+                }
+            }
+        });
+    } else {
+        let path = PathBuf::from(address);
+        debug!("serving rest api on {}", path.display());
+        socket::remove_stale_socket(&path)?;
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => spawn_handler(stream, &token, &tx, &state),
+                    Err(err) => warn!("error accepting rest api connection: {}", err),
+                }
+            }
+        });
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{route, setup, Action};
+    use crate::http::Request;
+    use crate::types::Update;
+
+    use tokio::sync::mpsc;
+
+    fn request(method: &str, path: &str, token: &str, body: &str) -> Request {
+        let data = format!(
+            "{} {} HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            token,
+            body.len(),
+            body
+        );
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(crate::http::read_request(&mut data.as_bytes()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_route() {
+        assert_eq!(
+            route(&request("GET", "/state", "secret", ""), "secret"),
+            Ok(Action::State)
+        );
+        assert_eq!(
+            route(
+                &request(
+                    "POST",
+                    "/status",
+                    "secret",
+                    r#"{"status": "3", "account": "fw"}"#
+                ),
+                "secret"
+            ),
+            Ok(Action::Update(Update::SetStatus(
+                Some("fw".to_string()),
+                "3".to_string(),
+                "remote"
+            )))
+        );
+        assert_eq!(
+            route(&request("POST", "/status/next", "secret", ""), "secret"),
+            Ok(Action::Update(Update::StatusNext(None)))
+        );
+        assert_eq!(
+            route(
+                &request(
+                    "POST",
+                    "/events/12/response",
+                    "secret",
+                    r#"{"accept": false}"#
+                ),
+                "secret"
+            ),
+            Ok(Action::Update(Update::EventResponse(12, false)))
+        );
+        assert_eq!(
+            route(
+                &request(
+                    "POST",
+                    "/alarms/7/response",
+                    "secret",
+                    r#"{"response_id": 2}"#
+                ),
+                "secret"
+            ),
+            Ok(Action::Update(Update::AlarmResponse(7, 2)))
+        );
+        assert_eq!(
+            route(
+                &request("GET", "/alarms/7/response", "secret", ""),
+                "secret"
+            )
+            .unwrap_err()
+            .status,
+            405
+        );
+
+        assert_eq!(
+            route(&request("GET", "/state", "wrong", ""), "secret")
+                .unwrap_err()
+                .status,
+            401
+        );
+        assert_eq!(
+            route(&request("GET", "/reload", "secret", ""), "secret")
+                .unwrap_err()
+                .status,
+            405
+        );
+        assert_eq!(
+            route(&request("POST", "/status", "secret", "{}"), "secret")
+                .unwrap_err()
+                .status,
+            400
+        );
+        assert_eq!(
+            route(
+                &request("POST", "/vehicles/1/status", "secret", r#"{"fms": 12}"#),
+                "secret"
+            )
+            .unwrap_err()
+            .status,
+            400
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_setup_address() {
+        let (tx, _rx) = mpsc::channel(1);
+        for address in ["0.0.0.0:0", "[::]:0", "192.0.2.1:9651"] {
+            let err = setup(address, "secret".to_string(), tx.clone())
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+        assert!(setup("127.0.0.1:0", "secret".to_string(), tx).await.is_ok());
+    }
+}
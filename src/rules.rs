@@ -0,0 +1,403 @@
+// session rules are only applied with the dbus interface
+#![cfg_attr(not(feature = "dbus-interface"), allow(dead_code))]
+
+use crate::types::Update;
+
+use chrono::{
+    Datelike, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Weekday,
+};
+use log::debug;
+use serde::{Deserialize, Deserializer};
+
+/// Time of day as "HH:MM" or "HH:MM:SS".
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(&value, "%H:%M:%S"))
+        .map(Some)
+        .map_err(|err| serde::de::Error::custom(format!("invalid time {}: {}", value, err)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionEvent {
+    Lock,
+    Unlock,
+    /// logind sets the idle hint of the session
+    Idle,
+    /// logind clears the idle hint of the session
+    Active,
+}
+
+/// Set a status when the session changes, optionally only on some days and times.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionRule {
+    pub on: SessionEvent,
+    /// weekdays like "mon", every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// start of the time of day, e.g. "07:30"
+    #[serde(default, deserialize_with = "time_of_day")]
+    pub from: Option<NaiveTime>,
+    /// end of the time of day, before `from` for rules over midnight
+    #[serde(default, deserialize_with = "time_of_day")]
+    pub until: Option<NaiveTime>,
+    /// id or name of the status
+    pub status: String,
+    /// account to change, the first one if missing
+    pub account: Option<String>,
+}
+
+/// A status change at a unix time from the schedule or a calendar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Switch {
+    pub time: i64,
+    /// account to change, the first one if missing
+    pub account: Option<String>,
+    /// id or name of the status
+    pub status: String,
+    /// shown in {status_source}
+    pub source: String,
+}
+
+pub fn local_time(unix: i64) -> NaiveDateTime {
+    Local
+        .timestamp_opt(unix, 0)
+        .single()
+        .map(|time| time.naive_local())
+        .unwrap_or_default()
+}
+
+pub fn unix_time(local: NaiveDateTime) -> i64 {
+    unix_time_in(&Local, local)
+}
+
+/// Unix time of a local time, a time skipped by a daylight saving change is moved forward by the skipped time.
+fn unix_time_in<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> i64 {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.timestamp(),
+        LocalResult::None => {
+            // the offset before the change, clocks change at most once a day
+            let before = tz
+                .offset_from_utc_datetime(&(local - chrono::Duration::days(1)))
+                .fix();
+            local.timestamp() - i64::from(before.local_minus_utc())
+        }
+    }
+}
+
+/// Set a status at a time of day on some weekdays or dates.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRule {
+    /// weekdays like "mon", every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// only on these dates like "2024-12-24" instead of the days
+    #[serde(default)]
+    pub dates: Vec<NaiveDate>,
+    /// not on these dates
+    #[serde(default)]
+    pub except: Vec<NaiveDate>,
+    #[serde(deserialize_with = "required_time_of_day")]
+    pub at: NaiveTime,
+    /// id or name of the status
+    pub status: String,
+    /// account to change, the first one if missing
+    pub account: Option<String>,
+}
+
+fn required_time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    time_of_day(deserializer).map(|time| time.expect("time_of_day always returns a time"))
+}
+
+impl ScheduleRule {
+    fn applies_on(&self, date: NaiveDate) -> bool {
+        if self.except.contains(&date) {
+            false
+        } else if !self.dates.is_empty() {
+            self.dates.contains(&date)
+        } else {
+            self.days.is_empty() || self.days.contains(&date.weekday())
+        }
+    }
+}
+
+/// Switches of the schedule after `from` until and including `until` in local time, the earliest first.
+pub fn switches(
+    schedule: &[ScheduleRule],
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Vec<(NaiveDateTime, &ScheduleRule)> {
+    let mut switches: Vec<(NaiveDateTime, &ScheduleRule)> = Vec::new();
+
+    for date in from
+        .date()
+        .iter_days()
+        .take_while(|date| *date <= until.date())
+    {
+        for rule in schedule.iter().filter(|rule| rule.applies_on(date)) {
+            let time = date.and_time(rule.at);
+            if from < time && time <= until {
+                switches.push((time, rule));
+            }
+        }
+    }
+    switches.sort_by_key(|(time, _)| *time);
+    switches
+}
+
+/// Switches of the schedule between the unix times `from` and `until`.
+pub fn schedule_switches(schedule: &[ScheduleRule], from: i64, until: i64) -> Vec<Switch> {
+    switches(schedule, local_time(from), local_time(until))
+        .into_iter()
+        .map(|(time, rule)| Switch {
+            time: unix_time(time),
+            account: rule.account.clone(),
+            status: rule.status.clone(),
+            source: "schedule".to_string(),
+        })
+        .collect()
+}
+
+/// Whether `time` is on one of the `days` (all if empty) and between `from` and `until`.
+pub fn in_window(
+    days: &[Weekday],
+    from: Option<NaiveTime>,
+    until: Option<NaiveTime>,
+    time: NaiveDateTime,
+) -> bool {
+    let day_ok = days.is_empty() || days.contains(&time.weekday());
+    let time = time.time();
+    let time_ok = match (from, until) {
+        (Some(from), Some(until)) if until < from => time >= from || time < until,
+        (from, until) => {
+            from.is_none_or(|from| time >= from) && until.is_none_or(|until| time < until)
+        }
+    };
+    day_ok && time_ok
+}
+
+impl SessionRule {
+    fn matches(&self, event: SessionEvent, time: NaiveDateTime) -> bool {
+        self.on == event && in_window(&self.days, self.from, self.until, time)
+    }
+}
+
+/// The status update of the first rule matching the event at local `time`.
+pub fn session_update(
+    rules: &[SessionRule],
+    event: SessionEvent,
+    time: NaiveDateTime,
+) -> Option<Update> {
+    match rules.iter().find(|rule| rule.matches(event, time)) {
+        Some(rule) => {
+            debug!("session event {:?} matches {:?}", event, rule);
+            Some(Update::RuleStatus(
+                rule.account.clone(),
+                rule.status.clone(),
+                "session",
+            ))
+        }
+        None => {
+            debug!("no rule for session event {:?}", event);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rules::{
+        session_update, switches, unix_time_in, ScheduleRule, SessionEvent, SessionRule,
+    };
+    use crate::types::Update;
+
+    use chrono::{FixedOffset, LocalResult, NaiveDate, NaiveDateTime, TimeZone};
+
+    /// UTC+1, UTC+2 from 2024-03-31 01:00 UTC like central Europe.
+    #[derive(Debug, Clone)]
+    struct SpringForward;
+
+    impl SpringForward {
+        fn change() -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2024, 3, 31)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap()
+        }
+    }
+
+    impl TimeZone for SpringForward {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            SpringForward
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let (winter, summer) = (
+                FixedOffset::east_opt(3600).unwrap(),
+                FixedOffset::east_opt(7200).unwrap(),
+            );
+            if *local < Self::change() + chrono::Duration::hours(1) {
+                LocalResult::Single(winter)
+            } else if *local < Self::change() + chrono::Duration::hours(2) {
+                LocalResult::None
+            } else {
+                LocalResult::Single(summer)
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            FixedOffset::east_opt(if *utc < Self::change() { 3600 } else { 7200 }).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_unix_time_gap() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let at =
+            |hour, minute| unix_time_in(&SpringForward, day.and_hms_opt(hour, minute, 0).unwrap());
+        let change = SpringForward::change().timestamp();
+
+        assert_eq!(at(1, 30), change - 1800);
+        // 02:30 does not exist, it is 03:30 in summer time
+        assert_eq!(at(2, 30), change + 1800);
+        assert_eq!(at(3, 30), change + 1800);
+        assert_eq!(at(4, 0), change + 3600);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule: Vec<ScheduleRule> = toml::from_str::<toml::Table>(
+            r#"
+            [[rule]]
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            except = ["2024-01-02"]
+            at = "07:30"
+            status = "at work"
+
+            [[rule]]
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            at = "16:30"
+            status = "available"
+
+            [[rule]]
+            dates = ["2024-01-06"]
+            at = "10:00"
+            status = "unavailable"
+            "#,
+        )
+        .unwrap()["rule"]
+            .clone()
+            .try_into()
+            .unwrap();
+
+        // from monday 12:00 until sunday
+        let from = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let until = NaiveDate::from_ymd_opt(2024, 1, 7)
+            .unwrap()
+            .and_hms_opt(23, 59, 0)
+            .unwrap();
+        let switches: Vec<String> = switches(&schedule, from, until)
+            .into_iter()
+            .map(|(time, rule)| format!("{} {}", time.format("%a %H:%M"), rule.status))
+            .collect();
+
+        assert_eq!(
+            switches,
+            vec![
+                "Mon 16:30 available",
+                "Tue 16:30 available",
+                "Wed 07:30 at work",
+                "Wed 16:30 available",
+                "Thu 07:30 at work",
+                "Thu 16:30 available",
+                "Fri 07:30 at work",
+                "Fri 16:30 available",
+                "Sat 10:00 unavailable",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_session_rules() {
+        let rules: Vec<SessionRule> = toml::from_str::<toml::Table>(
+            r#"
+            [[rule]]
+            on = "lock"
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            from = "17:00"
+            until = "06:00"
+            status = "available"
+
+            [[rule]]
+            on = "unlock"
+            from = "07:00"
+            until = "17:00"
+            status = "at work"
+            account = "fw"
+            "#,
+        )
+        .unwrap()["rule"]
+            .clone()
+            .try_into()
+            .unwrap();
+
+        // 2024-01-01 is a monday
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let sunday = NaiveDate::from_ymd_opt(2024, 1, 7).unwrap();
+
+        assert_eq!(
+            session_update(
+                &rules,
+                SessionEvent::Lock,
+                monday.and_hms_opt(17, 30, 0).unwrap()
+            ),
+            Some(Update::RuleStatus(None, "available".to_string(), "session"))
+        );
+        assert!(session_update(
+            &rules,
+            SessionEvent::Lock,
+            monday.and_hms_opt(12, 0, 0).unwrap()
+        )
+        .is_none());
+        assert!(session_update(
+            &rules,
+            SessionEvent::Lock,
+            sunday.and_hms_opt(18, 0, 0).unwrap()
+        )
+        .is_none());
+        assert_eq!(
+            session_update(
+                &rules,
+                SessionEvent::Unlock,
+                sunday.and_hms_opt(8, 0, 0).unwrap()
+            ),
+            Some(Update::RuleStatus(
+                Some("fw".to_string()),
+                "at work".to_string(),
+                "session"
+            ))
+        );
+        assert!(session_update(
+            &rules,
+            SessionEvent::Idle,
+            monday.and_hms_opt(18, 0, 0).unwrap()
+        )
+        .is_none());
+    }
+}
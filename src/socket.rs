@@ -0,0 +1,42 @@
+#![cfg(any(feature = "rest-api", feature = "control-socket"))]
+
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+/// Remove a socket left over from an earlier run, fail for anything else at the path.
+pub fn remove_stale_socket(path: &Path) -> Result<(), io::Error> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is no socket", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::remove_stale_socket;
+
+    #[test]
+    fn test_remove_stale_socket() {
+        let dir = std::env::temp_dir().join(format!("divera-status-socket-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "keep me").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+        assert!(file.exists());
+
+        let socket = dir.join("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        remove_stale_socket(&socket).unwrap();
+        assert!(!socket.exists());
+        remove_stale_socket(&socket).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+#![cfg(feature = "tray")]
+
+use crate::types::Update;
+use crate::AccountState;
+
+use ksni::menu::{StandardItem, SubMenu};
+use ksni::{Icon, MenuItem, ToolTip};
+use log::debug;
+use tokio::sync::mpsc;
+
+const ICON_SIZE: i32 = 32;
+
+/// What the tray shows of an account.
+#[derive(Debug, Default, Clone)]
+pub struct TrayAccount {
+    name: String,
+    status_name: String,
+    color_hex: String,
+    /// names and member counts of the shown statuses
+    counts: Vec<(String, u32)>,
+    /// ids and names of all statuses
+    statuses: Vec<(u32, String)>,
+}
+
+impl TrayAccount {
+    pub(crate) fn new(state: &AccountState) -> Self {
+        let status_id = *state.data.status().status_id();
+        let status = state.status_map.get(&status_id.to_string());
+        let name_of = |id: &u32| {
+            state
+                .status_map
+                .get(&id.to_string())
+                .map(|status| status.name().clone())
+                .unwrap_or_else(|| id.to_string())
+        };
+
+        let mut statuses: Vec<(u32, String)> = state
+            .status_map
+            .iter()
+            .filter_map(|(id, status)| Some((id.parse().ok()?, status.name().clone())))
+            .collect();
+        statuses.sort();
+
+        TrayAccount {
+            name: state.account.name.clone(),
+            status_name: status
+                .map(|status| status.name().clone())
+                .unwrap_or_default(),
+            color_hex: status
+                .map(|status| status.color_hex().clone())
+                .unwrap_or_default(),
+            counts: state
+                .resolved
+                .shown_statuses
+                .iter()
+                .map(|id| {
+                    let count = state
+                        .data
+                        .monitor()
+                        .basic()
+                        .get(&id.to_string())
+                        .map_or(0, |status| *status.count());
+                    (name_of(id), count)
+                })
+                .collect(),
+            statuses,
+        }
+    }
+}
+
+/// StatusNotifierItem with the color of the own status, the counts as tooltip and all statuses in the menu.
+pub struct Tray {
+    tx: mpsc::Sender<Update>,
+    accounts: Vec<TrayAccount>,
+}
+
+/// Red, green and blue of "3c3" or "33cc33", gray if invalid.
+fn rgb(color_hex: &str) -> [u8; 3] {
+    let color_hex = color_hex.trim_start_matches('#');
+    let digits: Vec<u8> = match color_hex.len() {
+        3 => color_hex
+            .chars()
+            .flat_map(|digit| [digit, digit])
+            .collect::<String>(),
+        6 => color_hex.to_string(),
+        _ => return [128, 128, 128],
+    }
+    .as_bytes()
+    .chunks(2)
+    .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+    .collect();
+
+    match digits[..] {
+        [red, green, blue] => [red, green, blue],
+        _ => [128, 128, 128],
+    }
+}
+
+/// A filled circle in the color.
+fn circle(color_hex: &str) -> Icon {
+    let [red, green, blue] = rgb(color_hex);
+    let radius = ICON_SIZE as f32 / 2.0;
+    let mut data = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let (dx, dy) = (x as f32 + 0.5 - radius, y as f32 + 0.5 - radius);
+            let alpha = if dx * dx + dy * dy <= radius * radius {
+                255
+            } else {
+                0
+            };
+            data.extend_from_slice(&[alpha, red, green, blue]);
+        }
+    }
+    Icon {
+        width: ICON_SIZE,
+        height: ICON_SIZE,
+        data,
+    }
+}
+
+impl Tray {
+    fn status_items(account: &TrayAccount) -> Vec<MenuItem<Self>> {
+        let name = (!account.name.is_empty()).then(|| account.name.clone());
+        account
+            .statuses
+            .iter()
+            .map(|(id, status_name)| {
+                let (name, id) = (name.clone(), *id);
+                StandardItem {
+                    label: status_name.replace('_', "__"),
+                    activate: Box::new(move |tray: &mut Self| {
+                        debug!("setting status {} from tray", id);
+                        let _ = tray.tx.blocking_send(Update::SetStatus(
+                            name.clone(),
+                            id.to_string(),
+                            "tray",
+                        ));
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect()
+    }
+}
+
+impl ksni::Tray for Tray {
+    fn id(&self) -> String {
+        "divera-status".to_string()
+    }
+
+    fn title(&self) -> String {
+        match self.accounts.first() {
+            Some(account) => format!("Divera: {}", account.status_name),
+            None => "Divera".to_string(),
+        }
+    }
+
+    fn icon_pixmap(&self) -> Vec<Icon> {
+        let color_hex = self
+            .accounts
+            .first()
+            .map(|account| account.color_hex.as_str())
+            .unwrap_or_default();
+        vec![circle(color_hex)]
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        let description = self
+            .accounts
+            .iter()
+            .map(|account| {
+                let counts = account
+                    .counts
+                    .iter()
+                    .map(|(name, count)| format!("{}: {}", name, count))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if account.name.is_empty() {
+                    counts
+                } else {
+                    format!("{}\n{}", account.name, counts)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        ToolTip {
+            title: self.title(),
+            description,
+            ..Default::default()
+        }
+    }
+
+    /// Left click reloads.
+    fn activate(&mut self, _x: i32, _y: i32) {
+        let _ = self.tx.blocking_send(Update::Reload);
+    }
+
+    /// Scrolling cycles the status of the first account.
+    fn scroll(&mut self, delta: i32, _dir: &str) {
+        let update = if delta > 0 {
+            Update::StatusNext(None)
+        } else {
+            Update::StatusPrev(None)
+        };
+        let _ = self.tx.blocking_send(update);
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        match &self.accounts[..] {
+            [account] => Self::status_items(account),
+            accounts => accounts
+                .iter()
+                .map(|account| {
+                    SubMenu {
+                        label: account.name.replace('_', "__"),
+                        submenu: Self::status_items(account),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Show the state after an update.
+pub(crate) fn update(handle: &ksni::Handle<Tray>, states: &[AccountState]) {
+    let accounts = states.iter().map(TrayAccount::new).collect();
+    handle.update(|tray| tray.accounts = accounts);
+}
+
+/// Show the tray icon, the handle updates it.
+pub fn setup(tx: mpsc::Sender<Update>, accounts: Vec<TrayAccount>) -> ksni::Handle<Tray> {
+    debug!("setting up tray icon");
+    let service = ksni::TrayService::new(Tray { tx, accounts });
+    let handle = service.handle();
+    service.spawn();
+    handle
+}
+
+#[cfg(test)]
+mod test {
+    use super::{circle, rgb, ICON_SIZE};
+
+    #[test]
+    fn test_icon() {
+        assert_eq!(rgb("3c3"), [0x33, 0xcc, 0x33]);
+        assert_eq!(rgb("#ff8000"), [0xff, 0x80, 0x00]);
+        assert_eq!(rgb("nope"), [128, 128, 128]);
+
+        let icon = circle("f00");
+        assert_eq!(icon.data.len(), (ICON_SIZE * ICON_SIZE * 4) as usize);
+        // transparent corner, red center
+        assert_eq!(icon.data[..4], [0, 0xff, 0, 0]);
+        let center = ((ICON_SIZE / 2 * ICON_SIZE + ICON_SIZE / 2) * 4) as usize;
+        assert_eq!(icon.data[center..center + 4], [255, 0xff, 0, 0]);
+    }
+}
@@ -1,16 +1,34 @@
-use std::fs::File;
-use std::io;
-use std::io::BufRead;
+use crate::calendar::CalendarConfig;
+use crate::config;
+use crate::config::{Account, ClickBinding, Config, MqttConfig, WebhookConfig};
+use crate::error::Error;
+use crate::rules::{ScheduleRule, SessionRule};
+
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
 
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use derive_getters::Getters;
 
 #[derive(Parser, Getters)]
 #[command(author, version, about, long_about = None)]
-#[command(group(ArgGroup::new("token_input").required(true).args(["token", "token_file"])))]
+#[command(group(ArgGroup::new("token_input").args(["token", "token_file", "token_command", "keyring"])))]
+#[command(subcommand_negates_reqs = true)]
 pub struct Arguments {
-    /// your personal api token for the divera instance
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
+    /// toml file with several accounts (replacing the token, server and status arguments), session rules, a status schedule, calendars, an mqtt broker and webhooks
+    #[arg(short, long)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// account from the config file to use for set, list, login and store-token
+    #[arg(short, long)]
+    pub(crate) account: Option<String>,
+
+    /// your personal api token for the divera instance, defaults to $DIVERA_TOKEN
     #[arg(short, long)]
     pub(crate) token: Option<String>,
 
@@ -18,23 +36,75 @@ pub struct Arguments {
     #[arg(short = 'f', long)]
     pub(crate) token_file: Option<PathBuf>,
 
-    /// update interval in seconds
-    #[arg(short, long, default_value_t = 30)]
-    pub(crate) interval: u8,
+    /// shell command that prints the api token as first line, e.g. "pass show divera"
+    #[arg(long)]
+    pub(crate) token_command: Option<String>,
+
+    /// read the api token from the secret service (see store-token), needs feature `secret-service`
+    #[arg(long)]
+    pub(crate) keyring: bool,
+
+    /// scheduled statuses do not replace a status changed by hand less than this before
+    #[arg(long, default_value = "30m", value_parser = parse_duration)]
+    pub(crate) schedule_grace: Duration,
+
+    /// show a desktop notification with notify-send for new news
+    #[arg(long)]
+    pub(crate) notify: bool,
+
+    /// serve prometheus metrics at http://<address>/metrics, e.g. "127.0.0.1:9650", needs feature `metrics`
+    #[arg(long)]
+    pub(crate) metrics: Option<SocketAddr>,
+
+    /// serve a json api at this loopback address (e.g. "127.0.0.1:9651") or unix socket path, needs feature `rest-api`
+    #[arg(long, requires = "rest_api_token_file")]
+    pub(crate) rest_api: Option<String>,
+
+    /// file with the bearer token for --rest-api as first line
+    #[arg(long)]
+    pub(crate) rest_api_token_file: Option<PathBuf>,
+
+    /// accept commands like "next" or "set 3" on $XDG_RUNTIME_DIR/divera-status.sock, needs feature `control-socket`
+    #[arg(long)]
+    pub(crate) control_socket: bool,
+
+    /// show a tray icon in the color of the own status with a menu to set it, needs feature `tray`
+    #[arg(long)]
+    pub(crate) tray: bool,
+
+    /// sqlite database to record the monitor and own status changes in and to read for history, needs feature `history`
+    #[arg(long)]
+    pub(crate) history: Option<PathBuf>,
+
+    /// update interval without open alarms, e.g. "90s" or "5m" (plain numbers are seconds)
+    #[arg(short, long, default_value = "30s", value_parser = parse_interval)]
+    pub(crate) interval: Duration,
+
+    /// update interval while an alarm is open
+    #[arg(long, default_value = "10s", value_parser = parse_interval)]
+    pub(crate) alarm_interval: Duration,
+
+    /// update interval after a failed update, doubled for every further failure up to 15 minutes
+    #[arg(long, default_value = "1m", value_parser = parse_interval)]
+    pub(crate) error_interval: Duration,
 
     /// divera instance to use
-    #[arg(long, default_value = "https://app.divera247.com")]
+    #[arg(long, default_value_t = config::default_server())]
     pub(crate) server: String,
 
-    /// statuses to be displayed (ids, comma separated)
-    #[arg(short, long)]
-    pub(crate) shown_statuses: String,
+    /// statuses to be displayed (ids or names, comma separated)
+    #[arg(short, long, required_unless_present = "config")]
+    pub(crate) shown_statuses: Option<String>,
 
-    /// order of statuses for quick change (ids, comma separated)
-    #[arg(short = 'o', long)]
-    pub(crate) status_order: String,
+    /// order of statuses for quick change (ids or names, comma separated)
+    #[arg(short = 'o', long, required_unless_present = "config")]
+    pub(crate) status_order: Option<String>,
 
-    /// format for updates to stdout, possible {}-values are: full_text, short_text, status_name, status_color, \[status_id], \[status_id]_count, \[status_id]_color
+    /// statuses counted as available in the status plan (ids or names, comma separated), defaults to the shown statuses
+    #[arg(long)]
+    pub(crate) available_statuses: Option<String>,
+
+    /// format for updates to stdout, possible {}-values are: full_text, short_text, status_name, status_color, news_unread, news_latest_title, next_event, status_source, next_planned_status, planned_available_\[n]h (members with an available status in n hours, up to 24), vehicles, vehicle_\[id]_fms, vehicle_\[id]_color, \[status], \[status]_count, \[status]_color where \[status] is the id or the name in lowercase with "_" instead of spaces; with --config all but full_text and short_text are prefixed with the account name, e.g. fw_status_name
     #[arg(
         short,
         long,
@@ -50,28 +120,179 @@ pub struct Arguments {
     #[arg(long)]
     pub(crate) no_pango: bool,
 
-    /// debug output
+    /// debug output on stderr, shorthand for `--log divera_status=debug`
     #[arg(long)]
     pub(crate) debug: bool,
+
+    /// log filter, e.g. "info,divera_status::api=trace" (defaults to $RUST_LOG or "warn")
+    #[arg(long)]
+    pub(crate) log: Option<String>,
+
+    /// write the log to this file instead of stderr
+    #[arg(long)]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// hide the names of other members in the log
+    #[arg(long)]
+    pub(crate) redact_names: bool,
+
+    /// show member counts instead of names in the output, can be toggled with a click action
+    #[arg(long)]
+    pub(crate) privacy: bool,
+
+    /// the --config file, parsed on first use
+    #[arg(skip)]
+    #[getter(skip)]
+    loaded_config: OnceLock<Config>,
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    match value.parse::<u64>() {
+        Ok(seconds) => Ok(Duration::from_secs(seconds)),
+        Err(_) => humantime::parse_duration(value).map_err(|err| err.to_string()),
+    }
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let duration = parse_duration(value)?;
+
+    if duration.is_zero() {
+        Err("interval must not be zero".to_string())
+    } else {
+        Ok(duration)
+    }
 }
 
 impl Arguments {
-    pub fn get_token(&self) -> Result<String, io::Error> {
-        let token: String = if let Some(token) = &self.token {
-            token.clone()
-        } else {
-            let mut buffer = String::new();
-            let file = File::open(
-                self.token_file
-                    .as_ref()
-                    .expect("neither token or token-file provided"),
-            )?;
-            let _ = io::BufReader::new(file).read_line(&mut buffer)?;
-            buffer.pop();
-            buffer
+    /// The account given by the token and status arguments.
+    fn cli_account(&self) -> Account {
+        Account {
+            name: String::new(),
+            token: self.token.clone(),
+            token_file: self.token_file.clone(),
+            token_command: self.token_command.clone(),
+            keyring: self.keyring,
+            server: self.server.clone(),
+            shown_statuses: self.shown_statuses.clone(),
+            status_order: self.status_order.clone(),
+            available_statuses: self.available_statuses.clone(),
+        }
+    }
+
+    /// The config file, read only once so all accessors see the same content.
+    fn load_config(&self) -> Result<&Config, Error> {
+        if let Some(config) = self.loaded_config.get() {
+            return Ok(config);
+        }
+
+        let config = match &self.config {
+            Some(config) => config::load(config)?,
+            None => Config::default(),
         };
-        Ok(token)
+        Ok(self.loaded_config.get_or_init(|| config))
     }
+
+    /// All accounts from the config file or the single account from the arguments.
+    pub fn accounts(&self) -> Result<Vec<Account>, Error> {
+        let accounts = &self.load_config()?.accounts;
+        if accounts.is_empty() {
+            Ok(vec![self.cli_account()])
+        } else {
+            Ok(accounts.clone())
+        }
+    }
+
+    /// Rules for session lock and idle changes from the config file.
+    pub fn session_rules(&self) -> Result<Vec<SessionRule>, Error> {
+        Ok(self.load_config()?.session_rules.clone())
+    }
+
+    /// Weekly status schedule from the config file.
+    pub fn schedule(&self) -> Result<Vec<ScheduleRule>, Error> {
+        Ok(self.load_config()?.schedule.clone())
+    }
+
+    /// MQTT broker from the config file.
+    pub fn mqtt(&self) -> Result<Option<MqttConfig>, Error> {
+        Ok(self.load_config()?.mqtt.clone())
+    }
+
+    /// Webhooks from the config file.
+    pub fn webhooks(&self) -> Result<Vec<WebhookConfig>, Error> {
+        Ok(self.load_config()?.webhooks.clone())
+    }
+
+    /// Actions for clicks on the i3blocks block from the config file.
+    pub fn clicks(&self) -> Result<Vec<ClickBinding>, Error> {
+        Ok(self.load_config()?.clicks.clone())
+    }
+
+    /// Calendar files that set the status from the config file.
+    pub fn calendars(&self) -> Result<Vec<CalendarConfig>, Error> {
+        Ok(self.load_config()?.calendars.clone())
+    }
+
+    /// The account selected with --account, defaults to the first one.
+    pub fn selected_account(&self) -> Result<Account, Error> {
+        let accounts = self.accounts()?;
+        match &self.account {
+            Some(name) => accounts
+                .into_iter()
+                .find(|account| &account.name == name)
+                .ok_or_else(|| Error::InvalidConfig(vec![format!("unknown account {}", name)])),
+            None => Ok(accounts.into_iter().next().expect("no accounts")),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// print the formatted output once and exit
+    Get,
+    /// set your status without a running daemon
+    Set {
+        /// id or name of the new status
+        status: String,
+    },
+    /// print ids, names and colors of your unit
+    List {
+        #[arg(value_enum)]
+        what: ListKind,
+    },
+    /// print how many members are planned to be available in each hour of the coming day
+    Plan,
+    /// print the upcoming status changes of the schedule and the calendars
+    Schedule {
+        /// number of days to show
+        #[arg(short, long, default_value_t = 7)]
+        days: u32,
+    },
+    /// print the upcoming events
+    Events {
+        /// print an iCalendar file instead of tab separated lines
+        #[arg(long)]
+        ics: bool,
+    },
+    /// print the average available members by weekday and hour and the minutes in each status this month from --history,
+    /// times without updates for more than three times --interval or --error-interval count as unknown status
+    History,
+    /// check server, token and configuration
+    #[command(visible_alias = "doctor")]
+    Check,
+    /// save the token in the secret service, read from stdin if no other token source is given
+    StoreToken,
+    /// log in with email and password and save the token to --token-file or --keyring
+    Login {
+        /// email or username, asked for if missing
+        #[arg(short, long)]
+        username: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ListKind {
+    Statuses,
+    Users,
 }
 
 #[non_exhaustive]
@@ -79,6 +300,22 @@ impl Arguments {
 #[derive(Debug, PartialEq)]
 pub enum Update {
     Reload,
-    StatusNext,
-    StatusPrev,
+    /// next status of the given account, the first one if none
+    StatusNext(Option<String>),
+    /// previous status of the given account, the first one if none
+    StatusPrev(Option<String>),
+    /// mark all news of all accounts as read
+    NewsRead,
+    /// accept (true) or decline the participation in the event with the id
+    EventResponse(u32, bool),
+    /// answer the alarm with the id with the response option with the id
+    AlarmResponse(u32, u32),
+    /// set the status of the given account (the first one if none) to an id or name by hand, with the source for {status_source}
+    SetStatus(Option<String>, String, &'static str),
+    /// like SetStatus, but by a rule, so it does not count as a change by hand
+    RuleStatus(Option<String>, String, &'static str),
+    /// set the FMS status of the vehicle with the id
+    VehicleStatus(u32, u8),
+    /// turn privacy mode on or off
+    TogglePrivacy,
 }
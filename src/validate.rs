@@ -0,0 +1,335 @@
+use crate::api::StatusMap;
+use crate::config::Account;
+use crate::error::Error;
+use crate::resolve;
+
+const BUILTIN_PLACEHOLDERS: [&str; 9] = [
+    "full_text",
+    "short_text",
+    "status_name",
+    "status_color",
+    "news_unread",
+    "news_latest_title",
+    "next_event",
+    "next_planned_status",
+    "status_source",
+];
+/// how far `planned_available_<n>h` can look ahead
+pub const PLAN_HOURS: i64 = 24;
+/// placeholders combining all accounts, never prefixed
+const COMBINED_PLACEHOLDERS: [&str; 2] = ["full_text", "short_text"];
+
+/// Statuses from the arguments, resolved to ids.
+#[derive(Debug, Default)]
+pub struct Resolved {
+    pub shown_statuses: Vec<u32>,
+    pub status_order: Vec<u32>,
+    pub available_statuses: Vec<u32>,
+    /// whether the display format shows vehicles of the account
+    pub vehicles: bool,
+}
+
+/// Names of all `{placeholders}` in a strfmt format string.
+fn placeholders(format: &str) -> Result<Vec<String>, String> {
+    let mut placeholders = Vec::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '{' => {
+                let mut key = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => key.push(c),
+                        None => return Err(format!("unclosed {{ in \"{}\"", format)),
+                    }
+                }
+                // strip format spec like {key:>5}
+                let key = key.split(':').next().unwrap_or_default().to_string();
+                placeholders.push(key);
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+            }
+            '}' => return Err(format!("single }} in \"{}\", use }}}} instead", format)),
+            _ => {}
+        }
+    }
+
+    Ok(placeholders)
+}
+
+fn is_known_placeholder(status_map: &StatusMap, placeholder: &str) -> bool {
+    if BUILTIN_PLACEHOLDERS.contains(&placeholder) {
+        return true;
+    }
+    if placeholder == "vehicles" {
+        return true;
+    }
+    if let Some(vehicle) = placeholder.strip_prefix("vehicle_").and_then(|vehicle| {
+        vehicle
+            .strip_suffix("_fms")
+            .or_else(|| vehicle.strip_suffix("_color"))
+    }) {
+        return vehicle.parse::<u32>().is_ok();
+    }
+    if let Some(hours) = placeholder
+        .strip_prefix("planned_available_")
+        .and_then(|hours| hours.strip_suffix('h'))
+    {
+        return hours
+            .parse::<i64>()
+            .is_ok_and(|hours| (0..=PLAN_HOURS).contains(&hours));
+    }
+
+    let status = placeholder
+        .strip_suffix("_count")
+        .or_else(|| placeholder.strip_suffix("_color"))
+        .unwrap_or(placeholder);
+
+    status_map.contains_key(status)
+        || resolve::unique_aliases(status_map)
+            .iter()
+            .any(|(status_alias, _)| status_alias == status)
+}
+
+/// The placeholder without the prefix of the account, None if it belongs to another account.
+fn strip_account<'a>(account: &Account, placeholder: &'a str) -> Option<&'a str> {
+    if account.name.is_empty() {
+        Some(placeholder)
+    } else {
+        placeholder
+            .strip_prefix(&account.name)
+            .and_then(|placeholder| placeholder.strip_prefix('_'))
+    }
+}
+
+fn is_known_account_placeholder(accounts: &[(&Account, &StatusMap)], placeholder: &str) -> bool {
+    COMBINED_PLACEHOLDERS.contains(&placeholder)
+        || accounts.iter().any(|(account, status_map)| {
+            strip_account(account, placeholder)
+                .is_some_and(|placeholder| is_known_placeholder(status_map, placeholder))
+        })
+}
+
+fn resolve_all(
+    status_map: &StatusMap,
+    option: &str,
+    list: Option<&str>,
+    problems: &mut Vec<String>,
+) -> Vec<u32> {
+    list.unwrap_or_default()
+        .split(',')
+        .filter(|status| !status.trim().is_empty())
+        .filter_map(|status| match resolve::resolve_status(status_map, status) {
+            Ok(id) => Some(id),
+            Err(err) => {
+                problems.push(format!("{}: {}", option, err));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Check the accounts and the display format against the data of the servers and collect all problems.
+///
+/// Returns the resolved statuses in the same order as the accounts.
+pub fn validate(
+    display_format: &str,
+    accounts: &[(&Account, &StatusMap)],
+) -> Result<Vec<Resolved>, Error> {
+    let mut problems: Vec<String> = Vec::new();
+    let mut resolved: Vec<Resolved> = Vec::new();
+
+    let placeholders = placeholders(display_format);
+
+    for (account, status_map) in accounts {
+        let (shown_option, order_option, available_option) = if account.name.is_empty() {
+            (
+                "--shown-statuses".to_string(),
+                "--status-order".to_string(),
+                "--available-statuses".to_string(),
+            )
+        } else {
+            (
+                format!("shown_statuses of account {}", account.name),
+                format!("status_order of account {}", account.name),
+                format!("available_statuses of account {}", account.name),
+            )
+        };
+
+        let shown_statuses = resolve_all(
+            status_map,
+            &shown_option,
+            account.shown_statuses.as_deref(),
+            &mut problems,
+        );
+        let status_order = resolve_all(
+            status_map,
+            &order_option,
+            account.status_order.as_deref(),
+            &mut problems,
+        );
+        let available_statuses = match &account.available_statuses {
+            Some(available_statuses) => resolve_all(
+                status_map,
+                &available_option,
+                Some(available_statuses),
+                &mut problems,
+            ),
+            None => shown_statuses.clone(),
+        };
+        let vehicles = placeholders.iter().flatten().any(|placeholder| {
+            !COMBINED_PLACEHOLDERS.contains(&placeholder.as_str())
+                && strip_account(account, placeholder)
+                    .is_some_and(|placeholder| placeholder.starts_with("vehicle"))
+        });
+        resolved.push(Resolved {
+            shown_statuses,
+            status_order,
+            available_statuses,
+            vehicles,
+        });
+    }
+
+    match placeholders {
+        Ok(placeholders) => {
+            for placeholder in placeholders {
+                if !is_known_account_placeholder(accounts, &placeholder) {
+                    problems.push(format!(
+                        "--display-format: unknown placeholder {{{}}}",
+                        placeholder
+                    ));
+                }
+            }
+        }
+        Err(err) => problems.push(format!("--display-format: {}", err)),
+    }
+
+    if problems.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(Error::InvalidConfig(problems))
+    }
+}
+
+/// Check that the accounts and statuses of rules exist, `option` names the rules in messages.
+pub fn validate_rules<'a>(
+    option: &str,
+    rules: impl Iterator<Item = (Option<&'a str>, &'a str)>,
+    accounts: &[(&Account, &StatusMap)],
+) -> Result<(), Error> {
+    let mut problems: Vec<String> = Vec::new();
+
+    for (account_name, status) in rules {
+        let account = match account_name {
+            Some(name) => accounts.iter().find(|(account, _)| account.name == name),
+            None => accounts.first(),
+        };
+        match account {
+            Some((_, status_map)) => {
+                if let Err(err) = resolve::resolve_status(status_map, status) {
+                    problems.push(format!("{}: {}", option, err));
+                }
+            }
+            None => problems.push(format!(
+                "{}: unknown account {}",
+                option,
+                account_name.unwrap_or_default()
+            )),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidConfig(problems))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::StatusMap;
+    use crate::error::Error;
+    use crate::resolve::test_status_map;
+    use crate::types::Arguments;
+    use crate::validate::{placeholders, validate};
+    use clap::Parser;
+
+    fn account(name: &str, shown_statuses: &str, status_order: &str) -> crate::config::Account {
+        let mut account = Arguments::parse_from([
+            "divera-status",
+            "--shown-statuses",
+            shown_statuses,
+            "--status-order",
+            status_order,
+        ])
+        .selected_account()
+        .unwrap();
+        account.name = name.to_string();
+        account
+    }
+
+    fn status_map() -> StatusMap {
+        test_status_map(&[("1", "Available"), ("2", "At Work"), ("3", "at-work")])
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders("{{\"text\": \"{full_text}\", \"n\": {1_count:>3}}}").unwrap(),
+            vec!["full_text", "1_count"]
+        );
+        assert!(placeholders("{full_text").is_err());
+        assert!(placeholders("full_text}").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let status_map = status_map();
+
+        let resolved = validate(
+            "{status_name} {available_count} {1_color} {planned_available_3h}",
+            &[(&account("", "available,2", "1,2"), &status_map)],
+        )
+        .unwrap();
+        assert_eq!(resolved[0].shown_statuses, vec![1, 2]);
+        assert_eq!(resolved[0].status_order, vec![1, 2]);
+        assert!(!resolved[0].vehicles);
+
+        match validate(
+            "{status_name} {at_work_count} {available} {planned_available_25h}",
+            &[(&account("", "available,busy", "1,7,at work"), &status_map)],
+        ) {
+            Err(Error::InvalidConfig(problems)) => assert_eq!(problems.len(), 5),
+            other => panic!("expected invalid config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_accounts() {
+        let status_map = status_map();
+        let fw = account("fw", "available", "1,2");
+        let rd = account("rd", "2", "1,2");
+
+        let resolved = validate(
+            "{full_text} {fw_status_name} {rd_available_count} {rd_vehicle_12_fms}",
+            &[(&fw, &status_map), (&rd, &status_map)],
+        )
+        .unwrap();
+        assert_eq!(resolved[1].shown_statuses, vec![2]);
+        assert!(!resolved[0].vehicles && resolved[1].vehicles);
+
+        match validate(
+            "{status_name} {thw_1} {fw_bogus} {rd_full_text}",
+            &[(&fw, &status_map), (&rd, &status_map)],
+        ) {
+            Err(Error::InvalidConfig(problems)) => assert_eq!(problems.len(), 3),
+            other => panic!("expected invalid config, got {:?}", other),
+        }
+    }
+}
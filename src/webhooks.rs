@@ -0,0 +1,302 @@
+#![cfg(feature = "webhooks")]
+
+use crate::api::{unix_now, REQUEST_TIMEOUT};
+use crate::api_types::Alarm;
+use crate::config::{WebhookConfig, WebhookEvent};
+use crate::error::Error;
+use crate::AccountState;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use reqwest::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use strfmt::strfmt;
+
+/// What is posted, also the placeholders of a body template.
+#[derive(Debug, Default, Serialize, PartialEq)]
+struct Payload {
+    event: Option<WebhookEvent>,
+    account: String,
+    time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alarm_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alarm_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alarm_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_name: Option<String>,
+}
+
+impl Payload {
+    fn alarm(event: WebhookEvent, account: &str, alarm: &Alarm) -> Self {
+        Payload {
+            event: Some(event),
+            account: account.to_string(),
+            time: unix_now(),
+            alarm_id: Some(*alarm.id()),
+            alarm_title: Some(alarm.title().clone()),
+            alarm_text: Some(alarm.text().clone()),
+            ..Default::default()
+        }
+    }
+
+    /// Body from the template with json escaped values, or the payload as json.
+    fn body(&self, template: Option<&str>) -> Result<String, String> {
+        let Some(template) = template else {
+            return Ok(serde_json::to_string(self).unwrap());
+        };
+
+        let serde_json::Value::Object(fields) = serde_json::to_value(self).unwrap() else {
+            unreachable!("payload is an object");
+        };
+        let mut values: HashMap<String, String> = [
+            "alarm_id",
+            "alarm_title",
+            "alarm_text",
+            "status_id",
+            "status_name",
+        ]
+        .into_iter()
+        .map(|key| (key.to_string(), String::new()))
+        .collect();
+        for (key, value) in fields {
+            let text = match value {
+                serde_json::Value::String(text) => text,
+                value => value.to_string(),
+            };
+            // without the quotes of the json string
+            let escaped = serde_json::to_string(&text).unwrap();
+            values.insert(key, escaped[1..escaped.len() - 1].to_string());
+        }
+        strfmt(template, &values).map_err(|err| err.to_string())
+    }
+}
+
+/// Own status and open alarms of an account at the last update.
+struct Known {
+    status_id: u32,
+    alarms: HashMap<u32, Alarm>,
+}
+
+/// Posts alarm and status changes to the configured urls.
+pub struct Webhooks {
+    hooks: Vec<WebhookConfig>,
+    client: Client,
+    known: HashMap<String, Known>,
+}
+
+fn signature(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Post the body, retrying with a doubled delay.
+async fn post(client: Client, hook: WebhookConfig, body: String) {
+    let mut delay = Duration::from_secs(1);
+    for attempt in 0..=hook.retries {
+        let mut request = client
+            .post(&hook.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone());
+        if let Some(secret) = &hook.secret {
+            request = request.header(
+                "X-Divera-Signature",
+                format!("sha256={}", signature(secret, &body)),
+            );
+        }
+
+        match request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => {
+                debug!("posted to webhook {}", hook.url);
+                return;
+            }
+            Err(err) if attempt < hook.retries => {
+                debug!(
+                    "webhook {} failed, retrying in {:?}: {}",
+                    hook.url, delay, err
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(err) => warn!("webhook {} failed: {}", hook.url, err),
+        }
+    }
+}
+
+impl Webhooks {
+    /// Check the body templates.
+    pub fn new(hooks: Vec<WebhookConfig>) -> Result<Self, Error> {
+        let problems: Vec<String> = hooks
+            .iter()
+            .filter_map(|hook| {
+                Payload::default()
+                    .body(hook.body.as_deref())
+                    .err()
+                    .map(|err| format!("webhook {}: invalid body: {}", hook.url, err))
+            })
+            .collect();
+        if !problems.is_empty() {
+            return Err(Error::InvalidConfig(problems));
+        }
+
+        Ok(Webhooks {
+            hooks,
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            known: HashMap::new(),
+        })
+    }
+
+    /// Changes since the last update of the account, nothing on the first one.
+    fn changes(
+        &mut self,
+        account: &str,
+        status_id: u32,
+        status_name: &str,
+        open: Vec<&Alarm>,
+    ) -> Vec<Payload> {
+        let alarms: HashMap<u32, Alarm> = open
+            .into_iter()
+            .map(|alarm| (*alarm.id(), alarm.clone()))
+            .collect();
+        let Some(known) = self.known.get(account) else {
+            self.known
+                .insert(account.to_string(), Known { status_id, alarms });
+            return Vec::new();
+        };
+
+        let mut changes: Vec<Payload> = alarms
+            .values()
+            .filter(|alarm| !known.alarms.contains_key(alarm.id()))
+            .map(|alarm| Payload::alarm(WebhookEvent::AlarmOpen, account, alarm))
+            .collect();
+        changes.extend(
+            known
+                .alarms
+                .values()
+                .filter(|alarm| !alarms.contains_key(alarm.id()))
+                .map(|alarm| Payload::alarm(WebhookEvent::AlarmClose, account, alarm)),
+        );
+        if known.status_id != status_id {
+            changes.push(Payload {
+                event: Some(WebhookEvent::StatusChange),
+                account: account.to_string(),
+                time: unix_now(),
+                status_id: Some(status_id),
+                status_name: Some(status_name.to_string()),
+                ..Default::default()
+            });
+        }
+
+        self.known
+            .insert(account.to_string(), Known { status_id, alarms });
+        changes
+    }
+
+    /// Post the changes of all accounts in the background.
+    pub(crate) fn update(&mut self, states: &[AccountState]) {
+        for state in states {
+            let status_id = *state.data.status().status_id();
+            let status_name = state
+                .status_map
+                .get(&status_id.to_string())
+                .map(|status| status.name().as_str())
+                .unwrap_or_default();
+            let changes = self.changes(
+                &state.account.name,
+                status_id,
+                status_name,
+                state.data.alarms().open().collect(),
+            );
+
+            for change in changes {
+                for hook in &self.hooks {
+                    if !change
+                        .event
+                        .is_some_and(|event| hook.events.contains(&event))
+                    {
+                        continue;
+                    }
+                    match change.body(hook.body.as_deref()) {
+                        Ok(body) => {
+                            tokio::spawn(post(self.client.clone(), hook.clone(), body));
+                        }
+                        Err(err) => warn!("webhook {}: {}", hook.url, err),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{signature, Payload, Webhooks};
+    use crate::api_types::Alarm;
+    use crate::config::WebhookEvent;
+
+    #[test]
+    fn test_changes() {
+        let mut webhooks = Webhooks::new(Vec::new()).unwrap();
+        let alarm = Alarm {
+            id: 5,
+            title: "B2 \"fire\"".to_string(),
+            text: String::new(),
+            date: 0,
+            closed: false,
+        };
+
+        assert!(webhooks.changes("", 1, "available", vec![]).is_empty());
+        let changes = webhooks.changes("", 1, "available", vec![&alarm]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].event, Some(WebhookEvent::AlarmOpen));
+        assert_eq!(
+            changes[0]
+                .body(Some(r#"{{"text": "{account}: {alarm_title}"}}"#))
+                .unwrap(),
+            r#"{"text": ": B2 \"fire\""}"#
+        );
+
+        let changes = webhooks.changes("", 3, "on the way", vec![]);
+        let events: Vec<_> = changes.iter().map(|change| change.event).collect();
+        assert_eq!(
+            events,
+            [
+                Some(WebhookEvent::AlarmClose),
+                Some(WebhookEvent::StatusChange)
+            ]
+        );
+        assert_eq!(changes[1].status_name.as_deref(), Some("on the way"));
+        assert!(changes[1]
+            .body(None)
+            .unwrap()
+            .contains(r#""event":"status_change""#));
+
+        assert!(Payload::default().body(Some("{unknown}")).is_err());
+    }
+
+    #[test]
+    fn test_signature() {
+        // echo -n "body" | openssl dgst -sha256 -hmac "key"
+        assert_eq!(
+            signature("key", "body"),
+            "515aae133b435d4000956731f68ae5cf5eb85d4f0dc6a546d2bfcd3595ec1ae1"
+        );
+    }
+}